@@ -0,0 +1,14 @@
+-- Answers written before the check may differ in case or spacing, e.g. 'coming' or 'NOT COMING',
+-- and anything else, such as '' or 'UNKNOWN', has no answer
+UPDATE invitee SET rsvp = CASE lower(regexp_replace(rsvp, '[^A-Za-z]', '', 'g'))
+    WHEN 'coming' THEN 'Coming'
+    WHEN 'notcoming' THEN 'NotComing'
+    WHEN 'maybe' THEN 'Maybe'
+    ELSE 'Unknown'
+  END
+  WHERE rsvp NOT IN ('Coming', 'NotComing', 'Maybe', 'Unknown');
+
+ALTER TABLE invitee
+  ALTER COLUMN rsvp SET DEFAULT 'Unknown',
+  DROP CONSTRAINT IF EXISTS invitee_rsvp_check,
+  ADD CONSTRAINT invitee_rsvp_check CHECK (rsvp IN ('Coming', 'NotComing', 'Maybe', 'Unknown'));
@@ -0,0 +1,21 @@
+-- A guest belongs to at most one household, and is never their own dependent. Relations written
+-- before this are cleaned up first, self relations dropped and only one household kept for a guest
+-- who was in several, the others being listed in the migration's notices
+DO $$
+DECLARE
+  dropped RECORD;
+BEGIN
+  FOR dropped IN
+    DELETE FROM relation a
+    USING relation b
+    WHERE a.parent = a.child
+      OR (a.child = b.child AND a.id > b.id AND b.parent <> b.child)
+    RETURNING a.parent, a.child
+  LOOP
+    RAISE NOTICE 'Removed % as a dependent of %', dropped.child, dropped.parent;
+  END LOOP;
+END $$;
+
+ALTER TABLE relation
+  ADD CONSTRAINT relation_child_key UNIQUE (child),
+  ADD CONSTRAINT relation_not_self CHECK (parent <> child);
@@ -0,0 +1,6 @@
+-- Free form labels for grouping guests, such as "family" or "uni"
+CREATE TABLE IF NOT EXISTS tag (
+  invitee TEXT NOT NULL REFERENCES invitee(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  tag TEXT NOT NULL,
+  PRIMARY KEY (invitee, tag)
+);
@@ -0,0 +1,8 @@
+-- Structured dietary requirements, kept alongside the free text invitee.dietary_requirements
+CREATE TABLE IF NOT EXISTS dietary_profile (
+  invitee TEXT NOT NULL PRIMARY KEY REFERENCES invitee(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  diet TEXT NOT NULL DEFAULT 'None'
+    CHECK (diet IN ('None', 'Vegetarian', 'Vegan', 'Pescatarian', 'Halal', 'Kosher', 'Other')),
+  allergies JSONB NOT NULL DEFAULT '[]',
+  notes TEXT NOT NULL DEFAULT ''
+);
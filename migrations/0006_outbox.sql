@@ -0,0 +1,11 @@
+-- Emails waiting to be delivered, written in the same transaction as the change they tell guests about
+CREATE TABLE IF NOT EXISTS outbox (
+  id TEXT NOT NULL PRIMARY KEY,
+  template TEXT NOT NULL,
+  recipient TEXT NOT NULL,
+  invitation JSONB NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS outbox_pending ON outbox (created_at) WHERE delivered_at IS NULL;
@@ -0,0 +1,21 @@
+-- When each invitation was emailed, so the first reminder waits a whole cadence after it
+ALTER TABLE email ADD COLUMN IF NOT EXISTS invite_sent_at TIMESTAMPTZ;
+UPDATE email SET invite_sent_at = now() WHERE inite_sent AND invite_sent_at IS NULL;
+
+-- A household's own reminder cadence and limit, keyed by the primary invitee, overriding the
+-- function's defaults where set
+CREATE TABLE IF NOT EXISTS reminder_settings (
+  household TEXT NOT NULL PRIMARY KEY REFERENCES invitee(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  cadence_days INTEGER CHECK (cadence_days > 0),
+  max_reminders INTEGER CHECK (max_reminders >= 0)
+);
+
+-- Every reminder queued for a household, and whether they had opened their invitation
+CREATE TABLE IF NOT EXISTS reminder (
+  id TEXT NOT NULL PRIMARY KEY,
+  household TEXT NOT NULL REFERENCES invitee(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  segment TEXT NOT NULL CHECK (segment IN ('opened', 'unopened')),
+  sent_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS reminder_household ON reminder (household, sent_at);
@@ -0,0 +1,6 @@
+-- A household's own cutoff for changing their answers, keyed by the primary invitee, overriding
+-- the function's global deadline
+CREATE TABLE IF NOT EXISTS rsvp_deadline (
+  household TEXT NOT NULL PRIMARY KEY REFERENCES invitee(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  deadline TIMESTAMPTZ NOT NULL
+);
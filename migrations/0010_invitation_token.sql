@@ -0,0 +1,12 @@
+-- The secrets in the links to each household's invitation, so the invitee's id is never sent to
+-- guests. A household has at most one active token, revoked ones are kept to see when a link
+-- stopped working
+CREATE TABLE IF NOT EXISTS invitation_token (
+  token TEXT NOT NULL PRIMARY KEY,
+  household TEXT NOT NULL REFERENCES invitee(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  revoked_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS invitation_token_active ON invitation_token (household)
+  WHERE revoked_at IS NULL;
@@ -0,0 +1,173 @@
+use super::*;
+use tracing::{event, Level};
+use uuid::Uuid;
+
+pub(crate) fn validate_name(invitee_fname: &str, invitee_lname: &str) -> Result<(), ApiErr> {
+    if invitee_fname.trim().is_empty() || invitee_lname.trim().is_empty() {
+        return Err(ApiErr::ArgumentErr(
+            "Invitee must have a first and last name".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn create_invitee<T: InviteeRepo>(
+    invitee: &NewInviteeATO,
+    db: &T,
+) -> Result<InviteeDTO, ApiErr> {
+    validate_name(&invitee.fname, &invitee.lname)?;
+
+    let invitee = InviteeDTO {
+        id: Uuid::new_v4().to_string(),
+        fname: invitee.fname.trim().to_string(),
+        lname: invitee.lname.trim().to_string(),
+        rsvp: invitee.rsvp,
+        dietary_requirements: invitee.dietary_requirements.clone(),
+        dietary: None,
+    };
+
+    let result = db.create_invitee(&invitee).await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = result {
+        event!(Level::ERROR, "Failed to create invitee");
+        return Err(err);
+    }
+
+    result
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn edit_invitee<T: InviteeRepo>(
+    invitee: &InviteeDTO,
+    db: &T,
+) -> Result<InviteeDTO, ApiErr> {
+    validate_name(&invitee.fname, &invitee.lname)?;
+    if let Some(dietary) = &invitee.dietary {
+        validate_dietary(dietary)?;
+    }
+
+    let result = db.edit_invitee(invitee).await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = result {
+        event!(Level::ERROR, "Failed to edit invitee");
+        return Err(err);
+    }
+
+    result
+}
+
+/// Deletes an invitee, refusing a primary invitee whose household still has dependents
+#[tracing::instrument(skip(db))]
+pub async fn delete_invitee<T: InviteeRepo + RelationRepo>(id: &str, db: &T) -> Result<(), ApiErr> {
+    ensure_no_dependents(id, db).await?;
+
+    let result = db.delete_invitee(id).await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = result {
+        event!(Level::ERROR, "Failed to delete invitee");
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+    const SINGLE: &str = "e01601bb-0647-410f-91b5-ca4df097c175";
+
+    #[tokio::test]
+    async fn should_create_invitee_with_new_id() {
+        let db = MemoryRepo::seeded();
+        let new_invitee = NewInviteeATO {
+            fname: " Test1 ".to_string(),
+            lname: "1".to_string(),
+            rsvp: Rsvp::Unknown,
+            dietary_requirements: "".to_string(),
+        };
+
+        let first = create_invitee(&new_invitee, &db)
+            .await
+            .expect("Should create invitee");
+        let second = create_invitee(&new_invitee, &db)
+            .await
+            .expect("Should create invitee");
+
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.fname, "Test1");
+        assert_eq!(db.get_invitee_by_id(&first.id).await.unwrap(), first);
+    }
+
+    #[tokio::test]
+    async fn should_reject_blank_name() {
+        let db = MemoryRepo::seeded();
+        let mut invitee = db.get_invitee_by_id(PRIMARY).await.unwrap();
+        invitee.fname = " ".to_string();
+
+        let result = edit_invitee(&invitee, &db).await;
+
+        assert!(matches!(result, Err(ApiErr::ArgumentErr(_))));
+    }
+
+    #[tokio::test]
+    async fn should_edit_invitee_name() {
+        let db = MemoryRepo::seeded();
+        let mut invitee = db.get_invitee_by_id(PRIMARY).await.unwrap();
+        invitee.fname = "Dave".to_string();
+        invitee.rsvp = Rsvp::Maybe;
+
+        let result = edit_invitee(&invitee, &db)
+            .await
+            .expect("Should edit invitee");
+
+        assert_eq!(result, invitee);
+        assert_eq!(db.get_invitee_by_id(PRIMARY).await.unwrap(), invitee);
+    }
+
+    #[tokio::test]
+    async fn should_delete_invitee_from_household() {
+        let db = MemoryRepo::seeded();
+
+        delete_invitee(DEPENDENT, &db)
+            .await
+            .expect("Should delete invitee");
+
+        let missing = delete_invitee(DEPENDENT, &db).await;
+        assert!(matches!(
+            missing,
+            Err(ApiErr::RepoErr(RepoErr::ItemNotFound(_)))
+        ));
+        let dependents = db.get_dependents(PRIMARY).await.unwrap();
+        assert!(!dependents.contains(&DEPENDENT.to_string()));
+    }
+
+    #[tokio::test]
+    async fn deleted_household_token_should_be_invalid() {
+        let db = MemoryRepo::seeded();
+        let token = household_token(SINGLE, &db).await.unwrap();
+
+        delete_invitee(SINGLE, &db)
+            .await
+            .expect("Should delete invitee");
+
+        assert!(matches!(
+            fetch_invitation(&token, None, &db).await,
+            Err(ApiErr::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_not_delete_primary_with_dependents() {
+        let db = MemoryRepo::seeded();
+
+        let result = delete_invitee(PRIMARY, &db).await;
+
+        assert!(matches!(result, Err(ApiErr::HouseholdConflict(_))));
+        assert!(db.get_invitee_by_id(PRIMARY).await.is_ok());
+        assert_eq!(db.get_dependents(PRIMARY).await.unwrap().len(), 2);
+    }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -8,6 +9,25 @@ pub enum ApiErr {
     RepoErr(#[from] RepoErr),
     #[error("Bad argument: {0}")]
     ArgumentErr(String),
+    #[error("Invitee {0} is not part of this invitation")]
+    NotInHousehold(String),
+    #[error("Service unavailable: {0}")]
+    Unavailable(String),
+    #[error("Admin access is required")]
+    Unauthorized,
+    #[error("Household conflict: {0}")]
+    HouseholdConflict(String),
+    #[error("Rsvps closed at {0}")]
+    RsvpClosed(DateTime<Utc>),
+    #[error("This invitation link is not valid, it may have been replaced")]
+    InvalidToken,
+}
+
+/// Who is making a request, admin functions are only available to the couple
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Guest,
+    Admin,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -15,20 +35,216 @@ pub enum ApiErr {
 #[serde(tag = "function", content = "params")]
 pub enum Payload {
     #[serde(rename = "fetchInvitation")]
-    FetchInvitation { id: String },
+    FetchInvitation { token: String },
     #[serde(rename = "updateInvitation")]
-    UpdateInvitation { invitation: InvitationATO },
+    UpdateInvitation {
+        token: String,
+        invitation: GuestInvitation,
+    },
+    /// Saves a household's answers after their deadline
+    #[serde(rename = "overrideInvitation")]
+    OverrideInvitation { invitation: InvitationATO },
+    #[serde(rename = "previewInvitation")]
+    PreviewInvitation { id: String },
+    #[serde(rename = "createInvitee")]
+    CreateInvitee { invitee: NewInviteeATO },
+    #[serde(rename = "editInvitee")]
+    EditInvitee { invitee: InviteeDTO },
+    #[serde(rename = "deleteInvitee")]
+    DeleteInvitee { id: String },
+    #[serde(rename = "attachDependent")]
+    AttachDependent { primary: String, dependent: String },
+    #[serde(rename = "detachDependent")]
+    DetachDependent { dependent: String },
+    #[serde(rename = "moveDependent")]
+    MoveDependent { dependent: String, primary: String },
+    #[serde(rename = "mergeHouseholds")]
+    MergeHouseholds { from: String, into: String },
+    #[serde(rename = "splitHousehold", rename_all = "camelCase")]
+    SplitHousehold {
+        primary: String,
+        new_primary: String,
+        #[serde(default)]
+        dependents: Vec<String>,
+    },
+    #[serde(rename = "getStats")]
+    GetStats,
+    #[serde(rename = "getCatererReport")]
+    GetCatererReport {
+        #[serde(default)]
+        format: ReportFormat,
+    },
+    #[serde(rename = "sendInvitations")]
+    SendInvitations,
+    #[serde(rename = "previewEmail")]
+    PreviewEmail { template: Template, id: String },
+    #[serde(rename = "setReminderSettings")]
+    SetReminderSettings { settings: ReminderSettings },
+    #[serde(rename = "getInvitationToken")]
+    GetInvitationToken { household: String },
+    /// Replaces the household's token, so their old link stops working
+    #[serde(rename = "rotateInvitationToken")]
+    RotateInvitationToken { household: String },
+    #[serde(rename = "revokeInvitationTokens")]
+    RevokeInvitationTokens { household: String },
+    #[serde(rename = "setRsvpDeadline")]
+    SetRsvpDeadline { deadline: RsvpDeadline },
+    #[serde(rename = "exportGuestList")]
+    ExportGuestList {
+        #[serde(default)]
+        format: ExportFormat,
+    },
+}
+
+impl Payload {
+    pub fn is_admin(&self) -> bool {
+        !matches!(
+            self,
+            Payload::FetchInvitation { .. } | Payload::UpdateInvitation { .. }
+        )
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(untagged)]
+pub enum Response {
+    GuestInvitation(GuestInvitation),
+    Invitation(InvitationATO),
+    Invitee(InviteeDTO),
+    Invitations(Vec<InvitationATO>),
+    GuestList(Vec<GuestExportRow>),
+    Text(String),
+    Stats(RsvpStats),
+    CatererReport(CatererReport),
+    SendReport(SendReport),
+    Email(RenderedEmail),
+    ReminderSettings(ReminderSettings),
+    RsvpDeadline(RsvpDeadline),
+    InvitationToken(InvitationToken),
+    Revoked { household: String, revoked: u64 },
+    Deleted { id: String },
 }
 
-#[tracing::instrument(skip(db_service))]
-pub async fn handle_request<T: InviteeRepo + RelationRepo>(
+/// Runs the requested function, `deadline` being the rsvp deadline for households without their
+/// own and `mail` being `None` when email is not configured. `params` are left out of the span,
+/// as they may hold an invitation token
+#[tracing::instrument(skip(params, db_service, mail))]
+pub async fn handle_request<
+    T: InviteeRepo
+        + RelationRepo
+        + GuestListRepo
+        + StatsRepo
+        + EmailRepo
+        + ReminderRepo
+        + DeadlineRepo
+        + TokenRepo
+        + UnitOfWork,
+    M: Mailer,
+>(
     params: Payload,
-    db_service: T,
-) -> Result<InvitationATO, ApiErr> {
+    role: Role,
+    db_service: &mut T,
+    deadline: Option<DateTime<Utc>>,
+    mail: Option<&Mail<M>>,
+) -> Result<Response, ApiErr> {
+    if params.is_admin() && role != Role::Admin {
+        return Err(ApiErr::Unauthorized);
+    }
+
     match params {
-        Payload::FetchInvitation { id } => fetch_invitation(&id, db_service).await,
-        Payload::UpdateInvitation { invitation } => {
-            update_invitation(&invitation, db_service).await
+        Payload::FetchInvitation { token } => fetch_invitation(&token, deadline, db_service)
+            .await
+            .map(Response::GuestInvitation),
+        Payload::UpdateInvitation { token, invitation } => {
+            update_invitation(&token, &invitation, deadline, db_service)
+                .await
+                .map(Response::GuestInvitation)
+        }
+        Payload::OverrideInvitation { invitation } => override_invitation(&invitation, db_service)
+            .await
+            .map(Response::Invitation),
+        Payload::PreviewInvitation { id } => preview_invitation(&id, db_service)
+            .await
+            .map(Response::Invitation),
+        Payload::CreateInvitee { invitee } => create_invitee(&invitee, db_service)
+            .await
+            .map(Response::Invitee),
+        Payload::EditInvitee { invitee } => edit_invitee(&invitee, db_service)
+            .await
+            .map(Response::Invitee),
+        Payload::DeleteInvitee { id } => delete_invitee(&id, db_service)
+            .await
+            .map(|_| Response::Deleted { id }),
+        Payload::AttachDependent { primary, dependent } => {
+            attach_dependent(&primary, &dependent, db_service)
+                .await
+                .map(Response::Invitations)
+        }
+        Payload::DetachDependent { dependent } => detach_dependent(&dependent, db_service)
+            .await
+            .map(Response::Invitations),
+        Payload::MoveDependent { dependent, primary } => {
+            move_dependent(&dependent, &primary, db_service)
+                .await
+                .map(Response::Invitations)
+        }
+        Payload::MergeHouseholds { from, into } => merge_households(&from, &into, db_service)
+            .await
+            .map(Response::Invitations),
+        Payload::SplitHousehold {
+            primary,
+            new_primary,
+            dependents,
+        } => split_household(&primary, &new_primary, &dependents, db_service)
+            .await
+            .map(Response::Invitations),
+        Payload::GetStats => get_stats(db_service).await.map(Response::Stats),
+        Payload::GetCatererReport { format } => {
+            let report = caterer_report(db_service).await?;
+            match format {
+                ReportFormat::Text => Ok(Response::Text(caterer_report_text(&report))),
+                ReportFormat::Csv => Ok(Response::Text(caterer_report_csv(&report))),
+                ReportFormat::Json => Ok(Response::CatererReport(report)),
+            }
+        }
+        Payload::SendInvitations => {
+            let mail = mail.ok_or_else(|| ApiErr::Unavailable("Email is not configured".into()))?;
+            send_invitations(deadline, db_service, mail)
+                .await
+                .map(Response::SendReport)
+        }
+        Payload::PreviewEmail { template, id } => {
+            let mail = mail.ok_or_else(|| ApiErr::Unavailable("Email is not configured".into()))?;
+            preview_email(template, &id, deadline, db_service, mail)
+                .await
+                .map(Response::Email)
+        }
+        Payload::SetReminderSettings { settings } => set_reminder_settings(&settings, db_service)
+            .await
+            .map(Response::ReminderSettings),
+        Payload::GetInvitationToken { household } => get_invitation_token(&household, db_service)
+            .await
+            .map(Response::InvitationToken),
+        Payload::RotateInvitationToken { household } => {
+            rotate_invitation_token(&household, db_service)
+                .await
+                .map(Response::InvitationToken)
+        }
+        Payload::RevokeInvitationTokens { household } => {
+            revoke_invitation_tokens(&household, db_service)
+                .await
+                .map(|revoked| Response::Revoked { household, revoked })
+        }
+        Payload::SetRsvpDeadline { deadline } => set_rsvp_deadline(&deadline, db_service)
+            .await
+            .map(Response::RsvpDeadline),
+        Payload::ExportGuestList { format } => {
+            let rows = export_guest_list(db_service).await?;
+            match format {
+                ExportFormat::Csv => Ok(Response::Text(guest_list_csv(&rows))),
+                ExportFormat::Json => Ok(Response::GuestList(rows)),
+            }
         }
     }
 }
@@ -38,12 +254,14 @@ mod test {
     use super::*;
     use serde_json::json;
 
+    const NO_MAIL: Option<&Mail<MemoryMailer>> = None;
+
     #[test]
     fn payload_should_deserialize() {
         let json = json!({
             "function":"fetchInvitation",
             "params": {
-                "id":"myid"
+                "token":"mytoken"
             }
         });
 
@@ -52,9 +270,198 @@ mod test {
         let payload = payload.expect("should parse properly");
 
         let correct = Payload::FetchInvitation {
-            id: String::from("myid"),
+            token: String::from("mytoken"),
         };
 
         assert_eq!(payload, correct);
     }
+
+    #[tokio::test]
+    async fn should_handle_fetch_request() {
+        let mut db = MemoryRepo::seeded();
+        let token = household_token("2e53abf4-323a-48ce-9ec2-0c0850c15523", &db)
+            .await
+            .unwrap();
+        let payload = Payload::FetchInvitation { token };
+        let deadline = "2027-03-01T00:00:00Z".parse().unwrap();
+
+        let response = handle_request(payload, Role::Guest, &mut db, Some(deadline), NO_MAIL)
+            .await
+            .expect("Should fetch invitation");
+
+        let invitation = db
+            .get_invitation("2e53abf4-323a-48ce-9ec2-0c0850c15523")
+            .await
+            .unwrap();
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(
+            response,
+            Response::GuestInvitation(GuestInvitation::new(&invitation, Some(deadline)))
+        );
+        assert_eq!(json["deadline"], "2027-03-01T00:00:00Z");
+        assert_eq!(json["primaryInvitee"]["fname"], "David");
+        assert!(json["primaryInvitee"].get("id").is_none());
+    }
+
+    #[tokio::test]
+    async fn preview_should_require_admin() {
+        let mut db = MemoryRepo::seeded();
+        let payload = || Payload::PreviewInvitation {
+            id: "2e53abf4-323a-48ce-9ec2-0c0850c15523".to_string(),
+        };
+
+        let guest = handle_request(payload(), Role::Guest, &mut db, None, NO_MAIL).await;
+        let admin = handle_request(payload(), Role::Admin, &mut db, None, NO_MAIL).await;
+
+        assert!(matches!(guest, Err(ApiErr::Unauthorized)));
+        assert!(admin.is_ok());
+    }
+
+    #[tokio::test]
+    async fn admin_functions_should_require_admin() {
+        let mut db = MemoryRepo::seeded();
+        let payload = Payload::DeleteInvitee {
+            id: "2e53abf4-323a-48ce-9ec2-0c0850c15523".to_string(),
+        };
+
+        let guest = handle_request(payload, Role::Guest, &mut db, None, NO_MAIL).await;
+
+        assert!(matches!(guest, Err(ApiErr::Unauthorized)));
+        assert!(db
+            .get_invitee_by_id("2e53abf4-323a-48ce-9ec2-0c0850c15523")
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn guest_should_not_answer_for_another_household() {
+        let mut db = MemoryRepo::seeded();
+        let token = household_token("2e53abf4-323a-48ce-9ec2-0c0850c15523", &db)
+            .await
+            .unwrap();
+        let invitation = db
+            .get_invitation("2e53abf4-323a-48ce-9ec2-0c0850c15523")
+            .await
+            .unwrap();
+        let outsider = db
+            .get_invitee_by_id("e01601bb-0647-410f-91b5-ca4df097c175")
+            .await
+            .unwrap();
+        let mut invitation = GuestInvitation::new(&invitation, None);
+        let mut answer = GuestInvitee::from(&outsider);
+        answer.rsvp = Rsvp::Coming;
+        invitation.dependents.push(answer);
+
+        let result = handle_request(
+            Payload::UpdateInvitation { token, invitation },
+            Role::Guest,
+            &mut db,
+            None,
+            NO_MAIL,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiErr::NotInHousehold(name)) if name == "Willian Kwong"));
+        let outsider = db
+            .get_invitee_by_id("e01601bb-0647-410f-91b5-ca4df097c175")
+            .await
+            .unwrap();
+        assert_eq!(outsider.rsvp, Rsvp::Unknown);
+    }
+
+    #[tokio::test]
+    async fn should_export_guest_list_as_json() {
+        let mut db = MemoryRepo::seeded();
+        let payload: Payload = serde_json::from_value(json!({
+            "function": "exportGuestList",
+            "params": { "format": "json" }
+        }))
+        .expect("should parse properly");
+
+        let response = handle_request(payload, Role::Admin, &mut db, None, NO_MAIL)
+            .await
+            .expect("Should export guest list");
+
+        assert!(matches!(response, Response::GuestList(rows) if rows.len() == 4));
+    }
+
+    #[tokio::test]
+    async fn send_invitations_should_need_mail() {
+        let mut db = MemoryRepo::seeded();
+
+        let result = handle_request(
+            Payload::SendInvitations,
+            Role::Admin,
+            &mut db,
+            None,
+            NO_MAIL,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiErr::Unavailable(_))));
+    }
+
+    #[tokio::test]
+    async fn should_preview_email_template() {
+        let mut db = MemoryRepo::seeded();
+        let mail = Mail {
+            mailer: MemoryMailer::new(),
+            from: "couple@example.com".to_string(),
+            invitation_url: "https://example.com/?token={token}".to_string(),
+        };
+        let token = household_token("2e53abf4-323a-48ce-9ec2-0c0850c15523", &db)
+            .await
+            .unwrap();
+        let payload: Payload = serde_json::from_value(json!({
+            "function": "previewEmail",
+            "params": { "template": "reminder", "id": "2e53abf4-323a-48ce-9ec2-0c0850c15523" }
+        }))
+        .expect("should parse properly");
+
+        let response = handle_request(payload, Role::Admin, &mut db, None, Some(&mail))
+            .await
+            .expect("Should preview email");
+
+        assert!(matches!(response, Response::Email(email)
+            if email.text.contains(&format!("https://example.com/?token={}", token))));
+        assert!(mail.mailer.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rsvp_should_queue_confirmation_without_sending() {
+        let mut db = MemoryRepo::seeded();
+        db.set_email(
+            "2e53abf4-323a-48ce-9ec2-0c0850c15523",
+            Some("david@example.com"),
+        )
+        .await
+        .unwrap();
+        let mail = Mail {
+            mailer: MemoryMailer::new(),
+            from: "couple@example.com".to_string(),
+            invitation_url: "https://example.com/?token={token}".to_string(),
+        };
+        let token = household_token("2e53abf4-323a-48ce-9ec2-0c0850c15523", &db)
+            .await
+            .unwrap();
+        let invitation = db
+            .get_invitation("2e53abf4-323a-48ce-9ec2-0c0850c15523")
+            .await
+            .unwrap();
+        let mut invitation = GuestInvitation::new(&invitation, None);
+        invitation.primary_invitee.rsvp = Rsvp::Coming;
+
+        let response = handle_request(
+            Payload::UpdateInvitation { token, invitation },
+            Role::Guest,
+            &mut db,
+            None,
+            Some(&mail),
+        )
+        .await;
+
+        assert!(response.is_ok());
+        assert!(mail.mailer.sent().is_empty());
+        assert_eq!(db.outbox()[0].1, OutboxStatus::Pending);
+    }
 }
@@ -0,0 +1,273 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use tracing::{event, Level};
+
+/// The group for guests who gave no dietary requirements
+pub const NO_REQUIREMENTS: &str = "none";
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Csv,
+    Json,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DietaryGroup {
+    pub requirement: String,
+    pub count: usize,
+    pub guests: Vec<String>,
+}
+
+/// The dietary requirements of every guest who is coming, largest group first
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CatererReport {
+    pub coming: usize,
+    pub groups: Vec<DietaryGroup>,
+}
+
+/// Puts free text requirements in a comparable form, so "Nut free, Vegan." and "vegan; nut free"
+/// are grouped together
+pub fn normalise_dietary_requirement(requirement: &str) -> String {
+    let mut parts: Vec<String> = requirement
+        .split([',', ';', '\n'])
+        .map(|e| {
+            e.split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ")
+                .trim_end_matches('.')
+                .to_lowercase()
+        })
+        .filter(|e| !matches!(e.as_str(), "" | "-" | "none" | "nil" | "n/a" | "na" | "no"))
+        .collect();
+    parts.sort();
+    parts.dedup();
+
+    if parts.is_empty() {
+        return NO_REQUIREMENTS.to_string();
+    }
+    parts.join(", ")
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn caterer_report<T: GuestListRepo>(db: &T) -> Result<CatererReport, ApiErr> {
+    let guests = db.get_guest_list().await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = guests {
+        event!(Level::ERROR, "Failed to get guest list");
+        return Err(err);
+    }
+
+    let mut report = CatererReport::default();
+    for guest in guests
+        .expect("Should handle err")
+        .iter()
+        .filter(|e| e.invitee.rsvp == Rsvp::Coming)
+    {
+        let requirement = normalise_dietary_requirement(&dietary_summary(&guest.invitee));
+        let name = format!("{} {}", guest.invitee.fname, guest.invitee.lname);
+        report.coming += 1;
+
+        match report
+            .groups
+            .iter_mut()
+            .find(|e| e.requirement == requirement)
+        {
+            Some(group) => {
+                group.count += 1;
+                group.guests.push(name);
+            }
+            None => report.groups.push(DietaryGroup {
+                requirement,
+                count: 1,
+                guests: vec![name],
+            }),
+        }
+    }
+
+    report.groups.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.requirement.cmp(&b.requirement))
+    });
+    Ok(report)
+}
+
+/// One row per group, with the guests' names separated by `;`
+pub fn caterer_report_csv(report: &CatererReport) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    writer
+        .write_record(["requirement", "count", "guests"])
+        .expect("Writing to memory should not fail");
+
+    for group in &report.groups {
+        writer
+            .write_record([
+                group.requirement.as_str(),
+                group.count.to_string().as_str(),
+                group.guests.join(";").as_str(),
+            ])
+            .expect("Writing to memory should not fail");
+    }
+
+    let csv = writer
+        .into_inner()
+        .expect("Writing to memory should not fail");
+    String::from_utf8(csv).expect("Csv should be written from strings")
+}
+
+/// A summary to print and hand to the caterer, guests with no requirements are only counted
+pub fn caterer_report_text(report: &CatererReport) -> String {
+    let mut text = format!("Dietary requirements for {} guests\n", report.coming);
+
+    for group in &report.groups {
+        text.push('\n');
+        writeln!(text, "{}: {}", group.requirement, group.count).expect("Should write to string");
+        if group.requirement == NO_REQUIREMENTS {
+            continue;
+        }
+        for guest in &group.guests {
+            writeln!(text, "  - {}", guest).expect("Should write to string");
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn answer(db: &MemoryRepo, id: &str, fname: &str, rsvp: Rsvp, dietary: &str) {
+        answer_with_profile(db, id, fname, rsvp, dietary, None).await;
+    }
+
+    async fn answer_with_profile(
+        db: &MemoryRepo,
+        id: &str,
+        fname: &str,
+        rsvp: Rsvp,
+        dietary: &str,
+        profile: Option<DietaryProfile>,
+    ) {
+        db.insert_invitee(id, fname, "Test");
+        db.edit_invitee(&InviteeDTO {
+            id: id.to_string(),
+            fname: fname.to_string(),
+            lname: "Test".to_string(),
+            rsvp,
+            dietary_requirements: dietary.to_string(),
+            dietary: profile,
+        })
+        .await
+        .expect("Should answer");
+    }
+
+    #[test]
+    fn should_normalise_dietary_requirements() {
+        assert_eq!(
+            normalise_dietary_requirement(" Nut free,  Vegan."),
+            "nut free, vegan"
+        );
+        assert_eq!(
+            normalise_dietary_requirement("vegan; nut   free"),
+            "nut free, vegan"
+        );
+        assert_eq!(normalise_dietary_requirement("N/A"), NO_REQUIREMENTS);
+        assert_eq!(normalise_dietary_requirement(""), NO_REQUIREMENTS);
+    }
+
+    #[tokio::test]
+    async fn should_group_guests_who_are_coming() {
+        let db = MemoryRepo::new();
+        answer(&db, "1", "Anna", Rsvp::Coming, "Vegan, nut free").await;
+        answer(&db, "2", "Ben", Rsvp::Coming, "nut free; vegan.").await;
+        answer(&db, "3", "Cara", Rsvp::Coming, "").await;
+        answer(&db, "4", "Dan", Rsvp::NotComing, "Vegan").await;
+        answer(&db, "5", "Eve", Rsvp::Maybe, "Halal").await;
+
+        let report = caterer_report(&db).await.expect("Should build report");
+
+        assert_eq!(report.coming, 3);
+        assert_eq!(
+            report.groups,
+            vec![
+                DietaryGroup {
+                    requirement: "nut free, vegan".to_string(),
+                    count: 2,
+                    guests: vec!["Anna Test".to_string(), "Ben Test".to_string()],
+                },
+                DietaryGroup {
+                    requirement: NO_REQUIREMENTS.to_string(),
+                    count: 1,
+                    guests: vec!["Cara Test".to_string()],
+                },
+            ]
+        );
+        assert_eq!(
+            caterer_report_csv(&report),
+            "requirement,count,guests\n\"nut free, vegan\",2,Anna Test;Ben Test\nnone,1,Cara Test\n"
+        );
+        assert_eq!(
+            caterer_report_text(&report),
+            "Dietary requirements for 3 guests\n\nnut free, vegan: 2\n  - Anna Test\n  - Ben Test\n\nnone: 1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_group_dietary_profiles_with_free_text() {
+        let db = MemoryRepo::new();
+        let profile = DietaryProfile {
+            diet: Diet::Vegan,
+            allergies: vec![Allergy {
+                allergen: "Peanut".to_string(),
+                severity: AllergySeverity::Severe,
+            }],
+            notes: "".to_string(),
+        };
+        answer_with_profile(&db, "1", "Anna", Rsvp::Coming, "", Some(profile)).await;
+        answer(
+            &db,
+            "2",
+            "Ben",
+            Rsvp::Coming,
+            "Vegan, peanut allergy (severe)",
+        )
+        .await;
+        answer_with_profile(
+            &db,
+            "3",
+            "Cara",
+            Rsvp::Coming,
+            "",
+            Some(DietaryProfile {
+                diet: Diet::Vegan,
+                ..Default::default()
+            }),
+        )
+        .await;
+
+        let report = caterer_report(&db).await.expect("Should build report");
+
+        assert_eq!(
+            report.groups,
+            vec![
+                DietaryGroup {
+                    requirement: "peanut allergy (severe), vegan".to_string(),
+                    count: 2,
+                    guests: vec!["Anna Test".to_string(), "Ben Test".to_string()],
+                },
+                DietaryGroup {
+                    requirement: "vegan".to_string(),
+                    count: 1,
+                    guests: vec!["Cara Test".to_string()],
+                },
+            ]
+        );
+    }
+}
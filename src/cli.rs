@@ -0,0 +1,149 @@
+use super::*;
+use std::time::SystemTime;
+
+const USAGE: &str =
+    "Usage: wedding_funcs [migrate | import <guests.csv> [--apply] | export [csv | json] | caterer [text | csv | json] | send-invitations | send-reminders | deliver-outbox]";
+
+/// Runs a one off command, such as migrating the db, instead of serving requests
+pub async fn run(args: &[String], app: App) -> Result<(), StdErr> {
+    match args[0].as_str() {
+        "migrate" => {
+            let mut client = app.connection.checkout().await?;
+            let applied = migrate(&mut client).await?;
+            if applied.is_empty() {
+                println!("Schema is up to date");
+            }
+            for migration in applied {
+                println!("Applied {:04}_{}", migration.version, migration.name);
+            }
+            Ok(())
+        }
+        "import" => {
+            let path = args.get(1).ok_or(USAGE)?;
+            let apply = match args.get(2).map(String::as_str) {
+                None => false,
+                Some("--apply") => true,
+                Some(_) => return Err(USAGE.into()),
+            };
+            let records = parse_guest_csv(std::fs::File::open(path)?)?;
+
+            let client = app.connection.checkout().await?;
+            let mut db = DB { client };
+            let changes = if apply {
+                import_guest_list(&records, &mut db).await
+            } else {
+                preview_guest_import(&records, &db).await
+            };
+            app.connection.checkin(db.client);
+
+            let changes = changes?;
+            for change in &changes {
+                println!("{}", change);
+            }
+            if changes.is_empty() {
+                println!("Guest list is up to date");
+            } else if !apply {
+                println!("Dry run, rerun with --apply to make these changes");
+            }
+            Ok(())
+        }
+        "export" => {
+            let format = match args.get(1).map(String::as_str) {
+                None | Some("csv") => ExportFormat::Csv,
+                Some("json") => ExportFormat::Json,
+                Some(_) => return Err(USAGE.into()),
+            };
+
+            let client = app.connection.checkout().await?;
+            let db = DB { client };
+            let rows = export_guest_list(&db).await;
+            app.connection.checkin(db.client);
+
+            let rows = rows?;
+            match format {
+                ExportFormat::Csv => print!("{}", guest_list_csv(&rows)),
+                ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+            }
+            Ok(())
+        }
+        "caterer" => {
+            let format = match args.get(1).map(String::as_str) {
+                None | Some("text") => ReportFormat::Text,
+                Some("csv") => ReportFormat::Csv,
+                Some("json") => ReportFormat::Json,
+                Some(_) => return Err(USAGE.into()),
+            };
+
+            let client = app.connection.checkout().await?;
+            let db = DB { client };
+            let report = caterer_report(&db).await;
+            app.connection.checkin(db.client);
+
+            let report = report?;
+            match format {
+                ReportFormat::Text => print!("{}", caterer_report_text(&report)),
+                ReportFormat::Csv => print!("{}", caterer_report_csv(&report)),
+                ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+            }
+            Ok(())
+        }
+        "send-invitations" => {
+            let mail = app.mail.as_ref().ok_or("Email is not configured")?;
+
+            let client = app.connection.checkout().await?;
+            let db = DB { client };
+            let report = send_invitations(app.config.rsvp_deadline, &db, mail).await;
+            app.connection.checkin(db.client);
+
+            let report = report?;
+            for invitee in &report.sent {
+                println!("Sent {}", invitee);
+            }
+            for failure in &report.failed {
+                println!(
+                    "Failed {} <{}>: {}",
+                    failure.invitee, failure.email, failure.reason
+                );
+            }
+            Ok(())
+        }
+        "send-reminders" => {
+            let client = app.connection.checkout().await?;
+            let mut db = DB { client };
+            let report = send_reminders(&app.config.reminders, SystemTime::now(), &mut db).await;
+            app.connection.checkin(db.client);
+
+            let report = report?;
+            for household in &report.opened {
+                println!("Reminded {}, who opened their invitation", household);
+            }
+            for household in &report.unopened {
+                println!(
+                    "Reminded {}, who has not opened their invitation",
+                    household
+                );
+            }
+            for household in &report.failed {
+                println!("Failed to remind {}", household);
+            }
+            println!("Reminders are queued, and sent by deliver-outbox");
+            Ok(())
+        }
+        "deliver-outbox" => {
+            let mail = app.mail.as_ref().ok_or("Email is not configured")?;
+
+            let client = app.connection.checkout().await?;
+            let db = DB { client };
+            let report = deliver_outbox(app.config.rsvp_deadline, &db, mail).await;
+            app.connection.checkin(db.client);
+
+            let report = report?;
+            println!(
+                "Delivered {}, retrying {}, dead-lettered {}",
+                report.delivered, report.retrying, report.dead
+            );
+            Ok(())
+        }
+        command => Err(format!("Unknown command {}\n{}", command, USAGE).into()),
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::{DateTime, Utc};
+use openssl::memcmp::eq;
+use std::env;
+
+use thiserror::Error;
+use wedding_funcs::{FileMailer, Mail, Mailer, ReminderPolicy, Role, SmtpMailer};
+
+#[derive(Error, Debug)]
+pub enum ConfigErr {
+    #[error("Missing environment variable {0}")]
+    MissingVar(&'static str),
+    #[error("Invalid ssl root certificate: {0}")]
+    InvalidCert(String),
+    #[error("Invalid smtp url: {0}")]
+    InvalidSmtpUrl(String),
+    #[error("WED_INVITATION_URL should have {{token}} in place of the invitation's token")]
+    InvalidInvitationUrl,
+    #[error("Environment variable {0} should be a whole number of at least {1}")]
+    InvalidNumber(&'static str, i32),
+    #[error(
+        "Environment variable {0} should be an RFC 3339 date, such as 2027-03-01T00:00:00+11:00"
+    )]
+    InvalidDate(&'static str),
+}
+
+/// Settings read from the environment once, when the function starts
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub postgres_uri: String,
+    pub ssl_cert_path: String,
+    /// Secret the couple sends to use admin functions, which are disabled when unset
+    pub admin_key: Option<String>,
+    /// Address emails are sent from, email is disabled unless this and `invitation_url` are set
+    pub mail_from: Option<String>,
+    /// Link to the website, with `{token}` in place of the invitation's token
+    pub invitation_url: Option<String>,
+    /// Server emails are delivered through, otherwise they are written to `mail_dir` or printed
+    pub smtp_url: Option<String>,
+    pub mail_dir: Option<String>,
+    /// Reminder cadence and limit for households without their own
+    pub reminders: ReminderPolicy,
+    /// When guests can no longer change their answers, unless their household has its own deadline
+    pub rsvp_deadline: Option<DateTime<Utc>>,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigErr> {
+        Ok(Self {
+            postgres_uri: var("WED_POSTGRES_URI")?,
+            ssl_cert_path: var("SSL_CERT_PATH")?,
+            admin_key: var("WED_ADMIN_KEY").ok(),
+            mail_from: var("WED_MAIL_FROM").ok(),
+            invitation_url: var("WED_INVITATION_URL").ok(),
+            smtp_url: var("WED_SMTP_URL").ok(),
+            mail_dir: var("WED_MAIL_DIR").ok(),
+            reminders: ReminderPolicy {
+                cadence_days: number_var(
+                    "WED_REMINDER_CADENCE_DAYS",
+                    1,
+                    ReminderPolicy::default().cadence_days,
+                )?,
+                max_reminders: number_var(
+                    "WED_MAX_REMINDERS",
+                    0,
+                    ReminderPolicy::default().max_reminders,
+                )?,
+            },
+            rsvp_deadline: date_var("WED_RSVP_DEADLINE")?,
+        })
+    }
+
+    pub fn mail(&self) -> Result<Option<Mail<Box<dyn Mailer>>>, ConfigErr> {
+        let (from, invitation_url) = match (&self.mail_from, &self.invitation_url) {
+            (Some(from), Some(invitation_url)) => (from.clone(), invitation_url.clone()),
+            _ => return Ok(None),
+        };
+        if !invitation_url.contains("{token}") {
+            return Err(ConfigErr::InvalidInvitationUrl);
+        }
+
+        let mailer: Box<dyn Mailer> = match (&self.smtp_url, &self.mail_dir) {
+            (Some(smtp_url), _) => Box::new(
+                SmtpMailer::from_url(smtp_url)
+                    .map_err(|e| ConfigErr::InvalidSmtpUrl(e.to_string()))?,
+            ),
+            (None, Some(mail_dir)) => Box::new(FileMailer::dir(mail_dir)),
+            (None, None) => Box::new(FileMailer::console()),
+        };
+
+        Ok(Some(Mail {
+            mailer,
+            from,
+            invitation_url,
+        }))
+    }
+
+    /// Compares the key in constant time, so it can not be guessed from how long a request takes
+    pub fn role(&self, admin_key: Option<&str>) -> Role {
+        match (admin_key, &self.admin_key) {
+            (Some(key), Some(admin_key))
+                if key.len() == admin_key.len() && eq(key.as_bytes(), admin_key.as_bytes()) =>
+            {
+                Role::Admin
+            }
+            _ => Role::Guest,
+        }
+    }
+}
+
+fn var(name: &'static str) -> Result<String, ConfigErr> {
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => Ok(value),
+        _ => Err(ConfigErr::MissingVar(name)),
+    }
+}
+
+/// Reads an optional number, which must be at least `min`
+fn number_var(name: &'static str, min: i32, default: i32) -> Result<i32, ConfigErr> {
+    let value = match var(name) {
+        Ok(value) => value,
+        Err(_) => return Ok(default),
+    };
+
+    match value.trim().parse() {
+        Ok(number) if number >= min => Ok(number),
+        _ => Err(ConfigErr::InvalidNumber(name, min)),
+    }
+}
+
+/// Reads an optional date
+fn date_var(name: &'static str) -> Result<Option<DateTime<Utc>>, ConfigErr> {
+    let value = match var(name) {
+        Ok(value) => value,
+        Err(_) => return Ok(None),
+    };
+
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|e| Some(e.with_timezone(&Utc)))
+        .map_err(|_| ConfigErr::InvalidDate(name))
+}
@@ -0,0 +1,75 @@
+use std::sync::{Mutex, PoisonError};
+
+use openssl::ssl::{SslConnector, SslMethod};
+use postgres_openssl::MakeTlsConnector;
+use tokio_postgres::Client;
+use tracing::{event, Level};
+
+use super::*;
+
+/// Number of idle clients kept around between invocations
+const MAX_IDLE: usize = 2;
+
+/// Postgres clients shared across invocations, connected lazily on first use
+pub struct Connection {
+    uri: String,
+    connector: MakeTlsConnector,
+    idle: Mutex<Vec<Client>>,
+}
+
+impl Connection {
+    pub fn new(config: &Config) -> Result<Self, ConfigErr> {
+        let mut builder = SslConnector::builder(SslMethod::tls())
+            .map_err(|e| ConfigErr::InvalidCert(e.to_string()))?;
+        builder
+            .set_ca_file(&config.ssl_cert_path)
+            .map_err(|e| ConfigErr::InvalidCert(e.to_string()))?;
+
+        Ok(Self {
+            uri: config.postgres_uri.clone(),
+            connector: MakeTlsConnector::new(builder.build()),
+            idle: Mutex::new(vec![]),
+        })
+    }
+
+    /// Takes an idle client, connecting again if there is none or the connection has since closed
+    pub async fn checkout(&self) -> Result<Client, tokio_postgres::Error> {
+        loop {
+            let client = self
+                .idle
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .pop();
+            match client {
+                Some(client) if !client.is_closed() => return Ok(client),
+                Some(_) => event!(Level::WARN, "Dropping closed connection"),
+                None => return self.connect().await,
+            }
+        }
+    }
+
+    /// Returns a client so it can be reused by the next invocation
+    pub fn checkin(&self, client: Client) {
+        if client.is_closed() {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < MAX_IDLE {
+            idle.push(client);
+        }
+    }
+
+    async fn connect(&self) -> Result<Client, tokio_postgres::Error> {
+        event!(Level::INFO, "Connecting to db");
+        let (client, connection) =
+            tokio_postgres::connect(&self.uri, self.connector.clone()).await?;
+
+        // Once this task ends the client reports closed, and is replaced on the next checkout
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                event!(Level::ERROR, "connection error: {}", e);
+            }
+        });
+        Ok(client)
+    }
+}
@@ -1,14 +1,19 @@
 use super::*;
 use async_trait::async_trait;
-use tokio_postgres::Client;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio_postgres::types::Json;
+use tokio_postgres::{Client, GenericClient, Row, Transaction};
 use tracing::{event, Level};
+use uuid::Uuid;
 
-pub struct DB<'a> {
-    pub client: &'a Client,
+/// Postgres backed repo, over either a plain client or an open transaction
+pub struct DB<C: GenericClient = Client> {
+    pub client: C,
 }
 
 #[async_trait]
-impl<'a> RelationRepo for DB<'a> {
+impl<C: GenericClient + Send + Sync> RelationRepo for DB<C> {
     async fn get_dependents(&self, id: &str) -> Result<Vec<String>, RepoErr> {
         let result = self
             .client
@@ -19,10 +24,145 @@ impl<'a> RelationRepo for DB<'a> {
         let result: Result<Vec<String>, _> = result.iter().map(|e| e.try_get(0)).collect();
         result.map_err(|e| RepoErr::DBFailure(e.to_string()))
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_parent(&self, id: &str) -> Result<Option<String>, RepoErr> {
+        let result = self
+            .client
+            .query_opt("SELECT parent FROM relation WHERE child = $1::TEXT", &[&id])
+            .await
+            .map_err(|e| RepoErr::DBFailure(e.to_string()))?;
+
+        result
+            .map(|e| e.try_get(0))
+            .transpose()
+            .map_err(|e| RepoErr::DBFailure(e.to_string()))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn add_relation(&self, parent: &str, child: &str) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute(
+                "INSERT INTO relation (parent, child) VALUES ($1::TEXT, $2::TEXT)",
+                &[&parent, &child],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to add relation");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn remove_relation(&self, parent: &str, child: &str) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute(
+                "DELETE FROM relation WHERE parent = $1::TEXT AND child = $2::TEXT",
+                &[&parent, &child],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to remove relation");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        if result.expect("Should handle err") == 0 {
+            return Err(RepoErr::ItemNotFound(child.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: GenericClient + Send + Sync> DB<C> {
+    /// Fills in the dietary profiles of invitees read from the `invitee` table, giving a default
+    /// profile to those without one
+    async fn read_dietary(&self, invitees: Vec<&mut InviteeDTO>) -> Result<(), RepoErr> {
+        let ids: Vec<&str> = invitees.iter().map(|e| e.id.as_str()).collect();
+        let result = self
+            .client
+            .query(
+                "SELECT invitee, diet, allergies, notes
+                FROM dietary_profile WHERE invitee IN (SELECT unnest($1::TEXT[]))",
+                &[&ids],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to get dietary profiles");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+        let result = result.expect("Should handle err");
+
+        let profiles: Result<Vec<(String, DietaryProfile)>, String> =
+            result.iter().map(dietary_from_row).collect();
+
+        if let Err(err) = profiles {
+            event!(
+                Level::ERROR,
+                "Failed to parse dietary profiles from db result"
+            );
+            return Err(RepoErr::DBFailure(err));
+        }
+        let profiles = profiles.expect("Should handle err");
+
+        for invitee in invitees {
+            let profile = profiles.iter().find(|(id, _)| *id == invitee.id);
+            invitee.dietary = Some(profile.map(|(_, e)| e.clone()).unwrap_or_default());
+        }
+        Ok(())
+    }
+
+    async fn write_dietary(&self, id: &str, dietary: &DietaryProfile) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute(
+                "INSERT INTO dietary_profile (invitee, diet, allergies, notes)
+                VALUES ($1::TEXT, $2::TEXT, $3::JSONB, $4::TEXT)
+                ON CONFLICT (invitee) DO UPDATE
+                SET diet = EXCLUDED.diet, allergies = EXCLUDED.allergies, notes = EXCLUDED.notes",
+                &[
+                    &id,
+                    &dietary.diet.as_str(),
+                    &Json(&dietary.allergies),
+                    &dietary.notes,
+                ],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to write dietary profile");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+fn dietary_from_row(row: &Row) -> Result<(String, DietaryProfile), String> {
+    let id: String = row.try_get(0).map_err(|e| e.to_string())?;
+    let diet: &str = row.try_get(1).map_err(|e| e.to_string())?;
+    let allergies: Json<Vec<Allergy>> = row.try_get(2).map_err(|e| e.to_string())?;
+    let notes: String = row.try_get(3).map_err(|e| e.to_string())?;
+
+    Ok((
+        id,
+        DietaryProfile {
+            diet: diet.parse()?,
+            allergies: allergies.0,
+            notes,
+        },
+    ))
 }
 
 #[async_trait]
-impl<'a> InviteeRepo for DB<'a> {
+impl<C: GenericClient + Send + Sync> InviteeRepo for DB<C> {
     #[tracing::instrument(skip(self))]
     async fn get_invitee_by_id(&self, id: &str) -> Result<InviteeDTO, RepoErr> {
         let result = self.client.query(
@@ -36,136 +176,1173 @@ impl<'a> InviteeRepo for DB<'a> {
         }
         let result = result.expect("Should handle err");
 
-        if let None = result.get(0) {
-            return Err(RepoErr::ItemNotFound(id.to_string()));
+        if result.is_empty() {
+            return Err(RepoErr::ItemNotFound(id.to_string()));
+        }
+
+        let result = result.first().unwrap();
+
+        let invitee = InviteeDTO::try_from(result).map_err(|e| RepoErr::DBFailure(e.to_string()));
+
+        if let Err(err) = invitee {
+            event!(
+                Level::ERROR,
+                msg = "Failed to parse db row into invitee",
+                ?result
+            );
+            return Err(err);
+        }
+
+        let mut invitee = invitee.expect("Should handle err");
+        self.read_dietary(vec![&mut invitee]).await?;
+        Ok(invitee)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_invitee_by_ids(&self, ids: &[&str]) -> Result<Vec<InviteeDTO>, RepoErr> {
+        let result = self
+            .client
+            .query(
+                "SELECT id, fname, lname, rsvp, dietary_requirements 
+                FROM invitee WHERE id IN (SELECT unnest($1::TEXT[]))",
+                &[&ids],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(
+                Level::ERROR,
+                "Failed to run query for fetching multiple invitees"
+            );
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+        let result = result.expect("Should handle err");
+
+        let invitees: Result<Vec<InviteeDTO>, &str> =
+            result.iter().map(InviteeDTO::try_from).collect();
+
+        if let Err(err) = invitees {
+            event!(Level::ERROR, "Failed to parse invitees from db result");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let mut invitees = invitees.unwrap();
+        self.read_dietary(invitees.iter_mut().collect()).await?;
+        Ok(invitees)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn update_invitee(&self, invitee: &UpdateInviteeParams) -> Result<InviteeDTO, RepoErr> {
+        let result = self
+            .client
+            .query(
+                "UPDATE invitee 
+                SET rsvp = $1::TEXT, dietary_requirements = $2::text
+                WHERE id = $3::TEXT
+                RETURNING id, fname, lname, rsvp, dietary_requirements
+            ",
+                &[&invitee.rsvp, &invitee.dietary_requirements, &invitee.id],
+            )
+            .await;
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to rsvp");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+        let result = result.expect("Should handle err");
+        let result = result.first();
+        if result.is_none() {
+            event!(Level::ERROR, "Failed to find invitee");
+            return Err(RepoErr::ItemNotFound(invitee.id.clone()));
+        }
+        let result = result.expect("Should handle None");
+
+        let updated = InviteeDTO::try_from(result).map_err(|e| RepoErr::DBFailure(e.to_string()));
+
+        if let Err(err) = updated {
+            event!(
+                Level::ERROR,
+                msg = "Failed to parse db row into invitee",
+                ?result
+            );
+            return Err(err);
+        }
+        let mut updated = updated.expect("Should handle err");
+
+        if let Some(dietary) = &invitee.dietary {
+            self.write_dietary(&invitee.id, dietary).await?;
+        }
+        self.read_dietary(vec![&mut updated]).await?;
+        Ok(updated)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn mark_opened(&self, ids: &[&str]) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute(
+                "UPDATE invitee SET invitation_opened = true WHERE id IN (SELECT unnest($1::TEXT[]))",
+                &[&ids],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(
+                Level::ERROR,
+                "Failed to update invitees' invitation_opened status"
+            );
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn create_invitee(&self, invitee: &InviteeDTO) -> Result<InviteeDTO, RepoErr> {
+        let result = self
+            .client
+            .query(
+                "INSERT INTO invitee (
+                    id,
+                    fname,
+                    lname,
+                    rsvp,
+                    dietary_requirements,
+                    invitation_opened
+                ) VALUES ($1::TEXT, $2::TEXT, $3::TEXT, $4::TEXT, $5::TEXT, false)
+                RETURNING id, fname, lname, rsvp, dietary_requirements",
+                &[
+                    &invitee.id,
+                    &invitee.fname,
+                    &invitee.lname,
+                    &invitee.rsvp,
+                    &invitee.dietary_requirements,
+                ],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to create invitee");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let mut created = invitee_from_rows(&invitee.id, &result.expect("Should handle err"))?;
+        if let Some(dietary) = &invitee.dietary {
+            self.write_dietary(&invitee.id, dietary).await?;
+        }
+        self.read_dietary(vec![&mut created]).await?;
+        Ok(created)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn edit_invitee(&self, invitee: &InviteeDTO) -> Result<InviteeDTO, RepoErr> {
+        let result = self
+            .client
+            .query(
+                "UPDATE invitee
+                SET fname = $2::TEXT, lname = $3::TEXT, rsvp = $4::TEXT, dietary_requirements = $5::TEXT
+                WHERE id = $1::TEXT
+                RETURNING id, fname, lname, rsvp, dietary_requirements",
+                &[
+                    &invitee.id,
+                    &invitee.fname,
+                    &invitee.lname,
+                    &invitee.rsvp,
+                    &invitee.dietary_requirements,
+                ],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to edit invitee");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let mut edited = invitee_from_rows(&invitee.id, &result.expect("Should handle err"))?;
+        if let Some(dietary) = &invitee.dietary {
+            self.write_dietary(&invitee.id, dietary).await?;
+        }
+        self.read_dietary(vec![&mut edited]).await?;
+        Ok(edited)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn delete_invitee(&self, id: &str) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute("DELETE FROM invitee WHERE id = $1::TEXT", &[&id])
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to delete invitee");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        if result.expect("Should handle err") == 0 {
+            return Err(RepoErr::ItemNotFound(id.to_string()));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_invitation(&self, id: &str) -> Result<InvitationATO, RepoErr> {
+        let result = self
+            .client
+            .query(
+                "WITH household AS (
+                    SELECT $1::TEXT AS id, true AS is_primary
+                    UNION ALL
+                    SELECT child, false FROM relation WHERE parent = $1::TEXT
+                )
+                SELECT invitee.id, fname, lname, rsvp, dietary_requirements, is_primary
+                FROM invitee JOIN household ON invitee.id = household.id
+                ORDER BY is_primary DESC",
+                &[&id],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to get invitation");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let mut invitation = invitation_from_rows(id, &result.expect("Should handle err"))?;
+        self.read_dietary(
+            std::iter::once(&mut invitation.primary_invitee)
+                .chain(invitation.dependents.iter_mut())
+                .collect(),
+        )
+        .await?;
+        Ok(invitation)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn open_invitation(&self, id: &str) -> Result<InvitationATO, RepoErr> {
+        let result = self
+            .client
+            .query(
+                "WITH household AS (
+                    SELECT $1::TEXT AS id, true AS is_primary
+                    UNION ALL
+                    SELECT child, false FROM relation WHERE parent = $1::TEXT
+                ), opened AS (
+                    UPDATE invitee SET invitation_opened = true
+                    FROM household
+                    WHERE invitee.id = household.id
+                    RETURNING invitee.id, fname, lname, rsvp, dietary_requirements, is_primary
+                )
+                SELECT * FROM opened ORDER BY is_primary DESC",
+                &[&id],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to open invitation");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let mut invitation = invitation_from_rows(id, &result.expect("Should handle err"))?;
+        self.read_dietary(
+            std::iter::once(&mut invitation.primary_invitee)
+                .chain(invitation.dependents.iter_mut())
+                .collect(),
+        )
+        .await?;
+        Ok(invitation)
+    }
+}
+
+#[async_trait]
+impl<C: GenericClient + Send + Sync> GuestListRepo for DB<C> {
+    #[tracing::instrument(skip(self))]
+    async fn get_guest_list(&self) -> Result<Vec<GuestListEntry>, RepoErr> {
+        let result = self
+            .client
+            .query(
+                "SELECT invitee.id, fname, lname, rsvp, dietary_requirements, relation.parent, email.email,
+                    ARRAY(SELECT tag FROM tag WHERE tag.invitee = invitee.id ORDER BY tag) AS tags,
+                    invitation_opened
+                FROM invitee
+                LEFT JOIN relation ON relation.child = invitee.id
+                LEFT JOIN email ON email.invitee = invitee.id
+                ORDER BY lname, fname, invitee.id",
+                &[],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to get guest list");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+        let result = result.expect("Should handle err");
+
+        let entries: Result<Vec<GuestListEntry>, &str> =
+            result.iter().map(GuestListEntry::try_from).collect();
+
+        if let Err(err) = entries {
+            event!(Level::ERROR, "Failed to parse guest list from db result");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let mut entries = entries.expect("Should handle err");
+        self.read_dietary(entries.iter_mut().map(|e| &mut e.invitee).collect())
+            .await?;
+        Ok(entries)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_email(&self, id: &str, email: Option<&str>) -> Result<(), RepoErr> {
+        let result = match email {
+            Some(email) => {
+                self.client
+                    .execute(
+                        "INSERT INTO email (invitee, email) VALUES ($1::TEXT, $2::TEXT)
+                        ON CONFLICT (invitee) DO UPDATE SET email = EXCLUDED.email",
+                        &[&id, &email],
+                    )
+                    .await
+            }
+            None => {
+                self.client
+                    .execute("DELETE FROM email WHERE invitee = $1::TEXT", &[&id])
+                    .await
+            }
+        };
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to set email");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_tags(&self, id: &str, tags: &[String]) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute("DELETE FROM tag WHERE invitee = $1::TEXT", &[&id])
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to clear tags");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let result = self
+            .client
+            .execute(
+                "INSERT INTO tag (invitee, tag) SELECT $1::TEXT, unnest($2::TEXT[])",
+                &[&id, &tags],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to set tags");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Counts the rsvps of the invitees aliased as `guest`, read back by `counts_from_row`
+const RSVP_COUNTS: &str = "COUNT(*),
+    COUNT(*) FILTER (WHERE guest.rsvp = 'Coming'),
+    COUNT(*) FILTER (WHERE guest.rsvp = 'NotComing'),
+    COUNT(*) FILTER (WHERE guest.rsvp = 'Maybe'),
+    COUNT(*) FILTER (WHERE guest.rsvp = 'Unknown'),
+    COUNT(*) FILTER (WHERE guest.rsvp = 'Unknown' AND guest.invitation_opened)";
+
+fn counts_from_row(row: &Row, start: usize) -> Result<RsvpCounts, tokio_postgres::Error> {
+    Ok(RsvpCounts {
+        total: row.try_get(start)?,
+        coming: row.try_get(start + 1)?,
+        not_coming: row.try_get(start + 2)?,
+        maybe: row.try_get(start + 3)?,
+        unanswered: row.try_get(start + 4)?,
+        opened_unanswered: row.try_get(start + 5)?,
+    })
+}
+
+fn stats_from_rows(
+    overall: &Row,
+    households: &[Row],
+    tags: &[Row],
+) -> Result<RsvpStats, tokio_postgres::Error> {
+    let households: Result<Vec<HouseholdStats>, _> = households
+        .iter()
+        .map(|e| {
+            Ok(HouseholdStats {
+                household: e.try_get(0)?,
+                fname: e.try_get(1)?,
+                lname: e.try_get(2)?,
+                counts: counts_from_row(e, 3)?,
+            })
+        })
+        .collect();
+
+    let tags: Result<Vec<TagStats>, _> = tags
+        .iter()
+        .map(|e| {
+            Ok(TagStats {
+                tag: e.try_get(0)?,
+                counts: counts_from_row(e, 1)?,
+            })
+        })
+        .collect();
+
+    Ok(RsvpStats {
+        overall: counts_from_row(overall, 0)?,
+        households: households?,
+        tags: tags?,
+    })
+}
+
+#[async_trait]
+impl<C: GenericClient + Send + Sync> EmailRepo for DB<C> {
+    #[tracing::instrument(skip(self))]
+    async fn get_unsent_emails(&self) -> Result<Vec<InviteeEmail>, RepoErr> {
+        let result = self
+            .client
+            .query(
+                "SELECT email.invitee, COALESCE(relation.parent, email.invitee), email.email
+                FROM email LEFT JOIN relation ON relation.child = email.invitee
+                WHERE NOT email.inite_sent
+                ORDER BY COALESCE(relation.parent, email.invitee), relation.parent IS NOT NULL,
+                    email.invitee",
+                &[],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to get unsent emails");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let emails: Result<Vec<InviteeEmail>, tokio_postgres::Error> = result
+            .expect("Should handle err")
+            .iter()
+            .map(|e| {
+                Ok(InviteeEmail {
+                    invitee: e.try_get(0)?,
+                    household: e.try_get(1)?,
+                    email: e.try_get(2)?,
+                })
+            })
+            .collect();
+
+        emails.map_err(|e| {
+            event!(Level::ERROR, "Failed to parse emails from db result");
+            RepoErr::DBFailure(e.to_string())
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn mark_invite_sent(&self, invitee: &str) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute(
+                "UPDATE email SET inite_sent = true, invite_sent_at = COALESCE(invite_sent_at, now())
+                WHERE invitee = $1::TEXT",
+                &[&invitee],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to mark invite sent");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        if result.expect("Should handle err") == 0 {
+            return Err(RepoErr::ItemNotFound(invitee.to_string()));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_household_email(&self, household: &str) -> Result<Option<String>, RepoErr> {
+        let result = self
+            .client
+            .query_opt(
+                "SELECT email.email
+                FROM email LEFT JOIN relation ON relation.child = email.invitee
+                WHERE email.invitee = $1::TEXT OR relation.parent = $1::TEXT
+                ORDER BY email.invitee = $1::TEXT DESC, email.invitee
+                LIMIT 1",
+                &[&household],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to get household email");
+            return Err(RepoErr::DBFailure(err.to_string()));
         }
 
-        let result = result.get(0).unwrap();
+        result
+            .expect("Should handle err")
+            .map(|e| e.try_get(0))
+            .transpose()
+            .map_err(|e| {
+                event!(Level::ERROR, "Failed to parse email from db result");
+                RepoErr::DBFailure(e.to_string())
+            })
+    }
+}
 
-        let update_result = self
+impl<C: GenericClient + Send + Sync> DB<C> {
+    /// Households who are due to be reminded, or only the given household
+    async fn query_reminder_candidates(
+        &self,
+        household: Option<&str>,
+    ) -> Result<Vec<ReminderCandidate>, RepoErr> {
+        // Each member of a household is emailed separately, so the household was invited when
+        // the first of them was
+        let result = self
             .client
             .query(
-                "UPDATE invitee SET invitation_opened = true WHERE id = $1::TEXT",
-                &[&id],
+                "SELECT household, email, opened, invited_at, reminders_sent, last_reminded_at,
+                    cadence_days, max_reminders
+                FROM (
+                    SELECT invitee.id AS household, invitee.invitation_opened AS opened,
+                        (SELECT email.email
+                        FROM email LEFT JOIN relation ON relation.child = email.invitee
+                        WHERE email.invitee = invitee.id OR relation.parent = invitee.id
+                        ORDER BY email.invitee = invitee.id DESC, email.invitee
+                        LIMIT 1) AS email,
+                        (SELECT bool_or(email.inite_sent)
+                        FROM email LEFT JOIN relation ON relation.child = email.invitee
+                        WHERE email.invitee = invitee.id OR relation.parent = invitee.id
+                        ) AS invited,
+                        (SELECT min(email.invite_sent_at)
+                        FROM email LEFT JOIN relation ON relation.child = email.invitee
+                        WHERE email.invitee = invitee.id OR relation.parent = invitee.id
+                        ) AS invited_at,
+                        (SELECT count(*)::INTEGER FROM reminder
+                        WHERE reminder.household = invitee.id) AS reminders_sent,
+                        (SELECT max(reminder.sent_at) FROM reminder
+                        WHERE reminder.household = invitee.id) AS last_reminded_at,
+                        reminder_settings.cadence_days, reminder_settings.max_reminders
+                    FROM invitee
+                    LEFT JOIN reminder_settings ON reminder_settings.household = invitee.id
+                    WHERE invitee.rsvp = 'Unknown'
+                        AND NOT EXISTS (SELECT 1 FROM relation WHERE relation.child = invitee.id)
+                        AND ($1::TEXT IS NULL OR invitee.id = $1::TEXT)
+                ) candidate
+                WHERE invited AND email IS NOT NULL
+                ORDER BY household",
+                &[&household],
             )
             .await;
 
-        if let Err(err) = update_result {
+        if let Err(err) = result {
             event!(
                 Level::ERROR,
-                "Failed to update invitation_opened status for invitee"
+                "Failed to run query to get reminder candidates"
             );
             return Err(RepoErr::DBFailure(err.to_string()));
         }
 
-        let invitee = InviteeDTO::try_from(result).map_err(|e| RepoErr::DBFailure(e.to_string()));
+        let candidates: Result<Vec<ReminderCandidate>, tokio_postgres::Error> = result
+            .expect("Should handle err")
+            .iter()
+            .map(|e| {
+                Ok(ReminderCandidate {
+                    household: e.try_get(0)?,
+                    email: e.try_get(1)?,
+                    opened: e.try_get(2)?,
+                    invited_at: e.try_get(3)?,
+                    reminders_sent: e.try_get(4)?,
+                    last_reminded_at: e.try_get(5)?,
+                    settings: ReminderSettings {
+                        household: e.try_get(0)?,
+                        cadence_days: e.try_get(6)?,
+                        max_reminders: e.try_get(7)?,
+                    },
+                })
+            })
+            .collect();
 
-        if let Err(err) = invitee {
+        candidates.map_err(|e| {
             event!(
                 Level::ERROR,
-                msg = "Failed to parse db row into invitee",
-                ?result
+                "Failed to parse reminder candidates from db result"
             );
-            return Err(err);
-        }
+            RepoErr::DBFailure(e.to_string())
+        })
+    }
+}
 
-        Ok(invitee.expect("Should handle err"))
+#[async_trait]
+impl<C: GenericClient + Send + Sync> ReminderRepo for DB<C> {
+    #[tracing::instrument(skip(self))]
+    async fn get_reminder_candidates(&self) -> Result<Vec<ReminderCandidate>, RepoErr> {
+        self.query_reminder_candidates(None).await
     }
 
     #[tracing::instrument(skip(self))]
-    async fn get_invitee_by_ids(&self, ids: &Vec<&str>) -> Result<Vec<InviteeDTO>, RepoErr> {
+    async fn lock_reminder_candidate(
+        &self,
+        household: &str,
+    ) -> Result<Option<ReminderCandidate>, RepoErr> {
         let result = self
             .client
             .query(
-                "SELECT id, fname, lname, rsvp, dietary_requirements 
-                FROM invitee WHERE id IN (SELECT unnest($1::TEXT[]))",
-                &[&ids],
+                "SELECT id FROM invitee WHERE id = $1::TEXT FOR UPDATE",
+                &[&household],
             )
             .await;
 
         if let Err(err) = result {
             event!(
                 Level::ERROR,
-                "Failed to run query for fetching multiple invitees"
+                "Failed to run query to lock reminder candidate"
             );
             return Err(RepoErr::DBFailure(err.to_string()));
         }
-        let result = result.expect("Should handle err");
 
-        let update_result = self
+        // Read once locked, so a reminder queued by a run which held the lock is counted
+        let candidates = self.query_reminder_candidates(Some(household)).await?;
+        Ok(candidates.into_iter().next())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn record_reminder(
+        &self,
+        household: &str,
+        segment: ReminderSegment,
+    ) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute(
+                "INSERT INTO reminder (id, household, segment) VALUES ($1::TEXT, $2::TEXT, $3::TEXT)",
+                &[&Uuid::new_v4().to_string(), &household, &segment.as_str()],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to record reminder");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_reminder_settings(&self, settings: &ReminderSettings) -> Result<(), RepoErr> {
+        let result = match (settings.cadence_days, settings.max_reminders) {
+            (None, None) => {
+                self.client
+                    .execute(
+                        "DELETE FROM reminder_settings WHERE household = $1::TEXT",
+                        &[&settings.household],
+                    )
+                    .await
+            }
+            _ => {
+                self.client
+                    .execute(
+                        "INSERT INTO reminder_settings (household, cadence_days, max_reminders)
+                        VALUES ($1::TEXT, $2::INTEGER, $3::INTEGER)
+                        ON CONFLICT (household) DO UPDATE
+                        SET cadence_days = EXCLUDED.cadence_days,
+                            max_reminders = EXCLUDED.max_reminders",
+                        &[
+                            &settings.household,
+                            &settings.cadence_days,
+                            &settings.max_reminders,
+                        ],
+                    )
+                    .await
+            }
+        };
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to set reminder settings");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<C: GenericClient + Send + Sync> DeadlineRepo for DB<C> {
+    #[tracing::instrument(skip(self))]
+    async fn get_rsvp_deadline(&self, household: &str) -> Result<Option<DateTime<Utc>>, RepoErr> {
+        let result = self
+            .client
+            .query_opt(
+                "SELECT deadline FROM rsvp_deadline WHERE household = $1::TEXT",
+                &[&household],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to get rsvp deadline");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        result
+            .expect("Should handle err")
+            .map(|e| e.try_get(0))
+            .transpose()
+            .map_err(|e| {
+                event!(Level::ERROR, "Failed to parse rsvp deadline from db result");
+                RepoErr::DBFailure(e.to_string())
+            })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_rsvp_deadline(
+        &self,
+        household: &str,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<(), RepoErr> {
+        let result = match deadline {
+            Some(deadline) => {
+                self.client
+                    .execute(
+                        "INSERT INTO rsvp_deadline (household, deadline)
+                        VALUES ($1::TEXT, $2::TIMESTAMPTZ)
+                        ON CONFLICT (household) DO UPDATE SET deadline = EXCLUDED.deadline",
+                        &[&household, &deadline],
+                    )
+                    .await
+            }
+            None => {
+                self.client
+                    .execute(
+                        "DELETE FROM rsvp_deadline WHERE household = $1::TEXT",
+                        &[&household],
+                    )
+                    .await
+            }
+        };
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to set rsvp deadline");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<C: GenericClient + Send + Sync> TokenRepo for DB<C> {
+    #[tracing::instrument(skip_all)]
+    async fn get_token_household(&self, token: &str) -> Result<Option<String>, RepoErr> {
+        let result = self
+            .client
+            .query_opt(
+                "SELECT household FROM invitation_token
+                WHERE token = $1::TEXT AND revoked_at IS NULL",
+                &[&token],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to get token household");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        result
+            .expect("Should handle err")
+            .map(|e| e.try_get(0))
+            .transpose()
+            .map_err(|e| {
+                event!(Level::ERROR, "Failed to parse household from db result");
+                RepoErr::DBFailure(e.to_string())
+            })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_token(&self, household: &str) -> Result<Option<String>, RepoErr> {
+        let result = self
+            .client
+            .query_opt(
+                "SELECT token FROM invitation_token
+                WHERE household = $1::TEXT AND revoked_at IS NULL",
+                &[&household],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to get token");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        result
+            .expect("Should handle err")
+            .map(|e| e.try_get(0))
+            .transpose()
+            .map_err(|e| {
+                event!(Level::ERROR, "Failed to parse token from db result");
+                RepoErr::DBFailure(e.to_string())
+            })
+    }
+
+    #[tracing::instrument(skip(self, token))]
+    async fn get_or_issue_token(&self, household: &str, token: &str) -> Result<String, RepoErr> {
+        // The active index makes a concurrent issue for the same household do nothing, so both
+        // return the token which was stored
+        let result = self
+            .client
+            .execute(
+                "INSERT INTO invitation_token (token, household) VALUES ($1::TEXT, $2::TEXT)
+                ON CONFLICT (household) WHERE revoked_at IS NULL DO NOTHING",
+                &[&token, &household],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to issue token");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let result = self
+            .client
+            .query_one(
+                "SELECT token FROM invitation_token
+                WHERE household = $1::TEXT AND revoked_at IS NULL",
+                &[&household],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to get token");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        result
+            .expect("Should handle err")
+            .try_get(0)
+            .map_err(|e| RepoErr::DBFailure(e.to_string()))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn revoke_tokens(&self, household: &str) -> Result<u64, RepoErr> {
+        let result = self
+            .client
+            .execute(
+                "UPDATE invitation_token SET revoked_at = now()
+                WHERE household = $1::TEXT AND revoked_at IS NULL",
+                &[&household],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to revoke tokens");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(result.expect("Should handle err"))
+    }
+}
+
+#[async_trait]
+impl<C: GenericClient + Send + Sync> OutboxRepo for DB<C> {
+    #[tracing::instrument(skip(self, message), fields(id = message.id))]
+    async fn enqueue(&self, message: &OutboxMessage) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute(
+                "INSERT INTO outbox (id, template, recipient, invitation)
+                VALUES ($1::TEXT, $2::TEXT, $3::TEXT, $4::JSONB)",
+                &[
+                    &message.id,
+                    &message.template.name(),
+                    &message.to,
+                    &Json(&message.invitation),
+                ],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(Level::ERROR, "Failed to run query to enqueue message");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn claim_pending(
+        &self,
+        limit: usize,
+        lease: Duration,
+    ) -> Result<Vec<OutboxMessage>, RepoErr> {
+        // Rows another worker has locked are skipped rather than waited on, then the lease keeps
+        // them hidden until they are delivered or it runs out
+        let result = self
             .client
             .query(
-                "UPDATE invitee SET invitation_opened = true WHERE id IN (SELECT unnest($1::TEXT[]))",
-                &[&ids],
+                "UPDATE outbox
+                SET attempts = attempts + 1,
+                    next_attempt_at = now() + $2::BIGINT * interval '1 second'
+                WHERE id IN (
+                    SELECT id FROM outbox
+                    WHERE status = 'pending' AND next_attempt_at <= now()
+                    ORDER BY next_attempt_at, created_at
+                    LIMIT $1::BIGINT
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id, template, recipient, invitation, attempts",
+                &[&(limit as i64), &(lease.as_secs() as i64)],
             )
             .await;
 
-        if let Err(err) = update_result {
+        if let Err(err) = result {
             event!(
                 Level::ERROR,
-                "Failed to update invitees' invitation_opened status"
+                "Failed to run query to claim pending messages"
             );
             return Err(RepoErr::DBFailure(err.to_string()));
         }
 
-        let invitees: Result<Vec<InviteeDTO>, &str> =
-            result.iter().map(|e| InviteeDTO::try_from(e)).collect();
+        let messages: Result<Vec<OutboxMessage>, String> = result
+            .expect("Should handle err")
+            .iter()
+            .map(outbox_message_from_row)
+            .collect();
 
-        if let Err(err) = invitees {
-            event!(Level::ERROR, "Failed to parse invitees from db result");
+        messages.map_err(|e| {
+            event!(Level::ERROR, "Failed to parse messages from db result");
+            RepoErr::DBFailure(e)
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn mark_delivered(&self, id: &str) -> Result<(), RepoErr> {
+        let result = self
+            .client
+            .execute(
+                "UPDATE outbox SET status = 'delivered', delivered_at = now(), last_error = NULL
+                WHERE id = $1::TEXT",
+                &[&id],
+            )
+            .await;
+
+        if let Err(err) = result {
+            event!(
+                Level::ERROR,
+                "Failed to run query to mark message delivered"
+            );
             return Err(RepoErr::DBFailure(err.to_string()));
         }
 
-        Ok(invitees.unwrap())
+        if result.expect("Should handle err") == 0 {
+            return Err(RepoErr::ItemNotFound(id.to_string()));
+        }
+
+        Ok(())
     }
 
     #[tracing::instrument(skip(self))]
-    async fn update_invitee(&self, invitee: &UpdateInviteeParams) -> Result<InviteeDTO, RepoErr> {
-        let rsvp = match invitee.rsvp {
-            Some(t) => {
-                if t {
-                    "Coming"
-                } else {
-                    "NotComing"
-                }
-            }
-            None => "Unknown",
+    async fn mark_failed(
+        &self,
+        id: &str,
+        error: &str,
+        retry_in: Option<Duration>,
+    ) -> Result<(), RepoErr> {
+        let status = match retry_in {
+            Some(_) => OutboxStatus::Pending,
+            None => OutboxStatus::Dead,
         };
+        let retry_in = retry_in.map(|e| e.as_secs() as i64).unwrap_or_default();
+
         let result = self
             .client
-            .query(
-                "UPDATE invitee 
-                SET rsvp = $1::TEXT, dietary_requirements = $2::text
-                WHERE id = $3::TEXT
-                RETURNING id, fname, lname, rsvp, dietary_requirements
-            ",
-                &[&rsvp, &invitee.dietary_requirements, &invitee.id],
+            .execute(
+                "UPDATE outbox
+                SET status = $2::TEXT, last_error = $3::TEXT,
+                    next_attempt_at = now() + $4::BIGINT * interval '1 second'
+                WHERE id = $1::TEXT",
+                &[&id, &status.as_str(), &error, &retry_in],
             )
             .await;
+
         if let Err(err) = result {
-            event!(Level::ERROR, "Failed to run query to rsvp");
+            event!(Level::ERROR, "Failed to run query to mark message failed");
             return Err(RepoErr::DBFailure(err.to_string()));
         }
-        let result = result.expect("Should handle err");
-        let result = result.get(0);
-        if let None = result {
-            event!(Level::ERROR, "Failed to find invitee");
-            return Err(RepoErr::ItemNotFound(invitee.id.clone()));
+
+        if result.expect("Should handle err") == 0 {
+            return Err(RepoErr::ItemNotFound(id.to_string()));
         }
-        let result = result.expect("Should handle None");
 
-        let invitee = InviteeDTO::try_from(result).map_err(|e| RepoErr::DBFailure(e.to_string()));
+        Ok(())
+    }
+}
 
-        if let Err(err) = invitee {
+fn outbox_message_from_row(row: &Row) -> Result<OutboxMessage, String> {
+    let id: String = row.try_get(0).map_err(|e| e.to_string())?;
+    let template: &str = row.try_get(1).map_err(|e| e.to_string())?;
+    let to: String = row.try_get(2).map_err(|e| e.to_string())?;
+    let invitation: Json<InvitationATO> = row.try_get(3).map_err(|e| e.to_string())?;
+    let attempts: i32 = row.try_get(4).map_err(|e| e.to_string())?;
+
+    Ok(OutboxMessage {
+        id,
+        template: template.parse()?,
+        to,
+        invitation: invitation.0,
+        attempts,
+    })
+}
+
+#[async_trait]
+impl<C: GenericClient + Send + Sync> StatsRepo for DB<C> {
+    #[tracing::instrument(skip(self))]
+    async fn get_stats(&self) -> Result<RsvpStats, RepoErr> {
+        let overall = self
+            .client
+            .query_one(&format!("SELECT {} FROM invitee guest", RSVP_COUNTS), &[])
+            .await;
+
+        if let Err(err) = overall {
+            event!(Level::ERROR, "Failed to run query to count rsvps");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let households = self
+            .client
+            .query(
+                &format!(
+                    "WITH guest AS (
+                        SELECT invitee.*, COALESCE(relation.parent, invitee.id) AS household
+                        FROM invitee LEFT JOIN relation ON relation.child = invitee.id
+                    )
+                    SELECT guest.household, primary_invitee.fname, primary_invitee.lname, {}
+                    FROM guest JOIN invitee primary_invitee ON primary_invitee.id = guest.household
+                    GROUP BY guest.household, primary_invitee.fname, primary_invitee.lname
+                    ORDER BY primary_invitee.lname, primary_invitee.fname, guest.household",
+                    RSVP_COUNTS
+                ),
+                &[],
+            )
+            .await;
+
+        if let Err(err) = households {
             event!(
                 Level::ERROR,
-                msg = "Failed to parse db row into invitee",
-                ?result
+                "Failed to run query to count rsvps by household"
             );
-            return Err(err);
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let tags = self
+            .client
+            .query(
+                &format!(
+                    "SELECT tag.tag, {}
+                    FROM tag JOIN invitee guest ON guest.id = tag.invitee
+                    GROUP BY tag.tag
+                    ORDER BY tag.tag",
+                    RSVP_COUNTS
+                ),
+                &[],
+            )
+            .await;
+
+        if let Err(err) = tags {
+            event!(Level::ERROR, "Failed to run query to count rsvps by tag");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        let stats = stats_from_rows(
+            &overall.expect("Should handle err"),
+            &households.expect("Should handle err"),
+            &tags.expect("Should handle err"),
+        );
+
+        if let Err(err) = stats {
+            event!(Level::ERROR, "Failed to parse stats from db result");
+            return Err(RepoErr::DBFailure(err.to_string()));
+        }
+
+        Ok(stats.expect("Should handle err"))
+    }
+}
+
+/// Parses the single invitee row returned by a write
+fn invitee_from_rows(id: &str, rows: &[Row]) -> Result<InviteeDTO, RepoErr> {
+    let row = rows.first();
+    if row.is_none() {
+        event!(Level::ERROR, "Failed to find invitee");
+        return Err(RepoErr::ItemNotFound(id.to_string()));
+    }
+
+    InviteeDTO::try_from(row.expect("Should handle None")).map_err(|e| {
+        event!(
+            Level::ERROR,
+            msg = "Failed to parse db row into invitee",
+            ?row
+        );
+        RepoErr::DBFailure(e.to_string())
+    })
+}
+
+/// Parses household rows, ordered with the primary invitee first, into an invitation
+fn invitation_from_rows(id: &str, rows: &[Row]) -> Result<InvitationATO, RepoErr> {
+    let is_primary = rows.first().map(|e| e.try_get::<_, bool>(5));
+    if !matches!(is_primary, Some(Ok(true))) {
+        event!(Level::ERROR, "Failed to find primary invitee");
+        return Err(RepoErr::ItemNotFound(id.to_string()));
+    }
+
+    let invitees: Result<Vec<InviteeDTO>, &str> = rows.iter().map(InviteeDTO::try_from).collect();
+
+    if let Err(err) = invitees {
+        event!(Level::ERROR, "Failed to parse invitees from db result");
+        return Err(RepoErr::DBFailure(err.to_string()));
+    }
+    let mut invitees = invitees.expect("Should handle err");
+
+    let primary_invitee = invitees.remove(0);
+    Ok(InvitationATO {
+        primary_invitee,
+        dependents: invitees,
+    })
+}
+
+#[async_trait]
+impl UnitOfWork for DB<Client> {
+    type Tx<'t> = DB<Transaction<'t>>;
+
+    #[tracing::instrument(skip(self))]
+    async fn begin(&mut self) -> Result<DB<Transaction<'_>>, RepoErr> {
+        let transaction = self.client.transaction().await;
+
+        if let Err(err) = transaction {
+            event!(Level::ERROR, "Failed to begin transaction");
+            return Err(RepoErr::DBFailure(err.to_string()));
         }
 
-        Ok(invitee.expect("Should handle err"))
+        Ok(DB {
+            client: transaction.expect("Should handle err"),
+        })
+    }
+}
+
+#[async_trait]
+impl<'a> Commit for DB<Transaction<'a>> {
+    #[tracing::instrument(skip(self))]
+    async fn commit(self) -> Result<(), RepoErr> {
+        self.client
+            .commit()
+            .await
+            .map_err(|e| RepoErr::DBFailure(e.to_string()))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn rollback(self) -> Result<(), RepoErr> {
+        self.client
+            .rollback()
+            .await
+            .map_err(|e| RepoErr::DBFailure(e.to_string()))
     }
 }
 
@@ -189,7 +1366,7 @@ mod tests {
             .unwrap();
         let connector = MakeTlsConnector::new(builder.build());
 
-        let (client, connection) = tokio_postgres::connect(&format!("{}", uri), connector)
+        let (client, connection) = tokio_postgres::connect(&uri, connector)
             .await
             .expect("Connection should not fail");
 
@@ -201,6 +1378,23 @@ mod tests {
         client
     }
 
+    /// Creates an invitee with a new id to test against, which the test deletes when it is done
+    async fn insert_test_invitee(db: &DB, rsvp: Rsvp) -> String {
+        let id: String = Uuid::new_v4().to_string();
+
+        db.create_invitee(&InviteeDTO {
+            id: id.clone(),
+            fname: "Test1".to_string(),
+            lname: "1".to_string(),
+            rsvp,
+            dietary_requirements: "".to_string(),
+            dietary: None,
+        })
+        .await
+        .expect("Should create invitee");
+        id
+    }
+
     #[tokio::test]
     async fn should_get_invitee_by_id() {
 
@@ -222,7 +1416,7 @@ mod tests {
                     $1::TEXT,
                     'Test1',
                     '1',
-                    'UNKNOWN',
+                    'Unknown',
                     'something',
                     false
                 );
@@ -233,18 +1427,18 @@ mod tests {
             .expect("Insert query should not fail");
 
         // test
-        let db = DB { client: &client };
+        let db = DB { client };
         let invite = db.get_invitee_by_id(&id).await;
         let invite = invite.expect("Should retrieve sent");
 
         assert_eq!(invite.id, id);
         assert_eq!(invite.fname, "Test1".to_owned());
         assert_eq!(invite.lname, "1".to_owned());
-        assert_eq!(invite.rsvp, None);
+        assert_eq!(invite.rsvp, Rsvp::Unknown);
         assert_eq!(invite.dietary_requirements, "something".to_owned());
 
         //cleanup
-        client
+        db.client
             .query("DELETE FROM invitee WHERE invitee.id = $1::TEXT", &[&id])
             .await
             .expect("Should delete created");
@@ -273,7 +1467,7 @@ mod tests {
                     $1::TEXT,
                     'Test1',
                     '1',
-                    'UNKNOWN',
+                    'Unknown',
                     'something',
                     false
                 );
@@ -297,7 +1491,7 @@ mod tests {
                     $1::TEXT,
                     'Test1',
                     '1',
-                    'UNKNOWN',
+                    'Unknown',
                     'something',
                     false
                 );
@@ -321,7 +1515,7 @@ mod tests {
                     $1::TEXT,
                     'Test1',
                     '1',
-                    'UNKNOWN',
+                    'Unknown',
                     'something',
                     false
                 );
@@ -332,16 +1526,16 @@ mod tests {
             .expect("insert shouuld not fail");
 
         // test
-        let db = DB { client: &client };
+        let db = DB { client };
         let invites = db
-            .get_invitee_by_ids(&vec![&id, &id2, &id3])
+            .get_invitee_by_ids(&[&id, &id2, &id3])
             .await
             .expect("Should retrieve invites");
 
         assert_eq!(invites.len(), 3);
 
         //cleanup
-        client
+        db.client
             .query(
                 "DELETE FROM invitee WHERE invitee.id IN (SELECT unnest($1::TEXT[]))",
                 &[&vec![id, id2, id3]],
@@ -370,7 +1564,7 @@ mod tests {
                     $1::TEXT,
                     'Test1',
                     '1',
-                    'UNKNOWN',
+                    'Unknown',
                     'something',
                     false
                 );
@@ -381,11 +1575,19 @@ mod tests {
             .expect("Insert query should not fail");
 
         // test
-        let db = DB { client: &client };
+        let db = DB { client };
         let params = UpdateInviteeParams {
             id: id.clone(),
-            rsvp: Some(true),
+            rsvp: Rsvp::Coming,
             dietary_requirements: "Something new".to_string(),
+            dietary: Some(DietaryProfile {
+                diet: Diet::Vegan,
+                allergies: vec![Allergy {
+                    allergen: "Peanuts".to_string(),
+                    severity: AllergySeverity::Severe,
+                }],
+                notes: "Carries an epipen".to_string(),
+            }),
         };
         let invite = db
             .update_invitee(&params)
@@ -393,13 +1595,555 @@ mod tests {
             .expect("Should update invite");
 
         assert_eq!(invite.id, id);
-        assert_eq!(invite.rsvp, Some(true));
+        assert_eq!(invite.rsvp, Rsvp::Coming);
         assert_eq!(invite.dietary_requirements, "Something new".to_string());
+        assert_eq!(invite.dietary, params.dietary);
+        assert_eq!(
+            db.get_invitee_by_id(&id).await.unwrap().dietary,
+            params.dietary
+        );
 
         //cleanup
+        db.client
+            .query("DELETE FROM invitee WHERE invitee.id = $1::TEXT", &[&id])
+            .await
+            .expect("Should delete created");
+    }
+
+    #[tokio::test]
+    async fn should_rollback_transaction_on_failed_dependent() {
+        let client = get_pg_client().await;
+        let id: String = Uuid::new_v4().to_string();
+        let missing_id: String = Uuid::new_v4().to_string();
+
+        // setup
         client
+            .query(
+                "
+                INSERT INTO invitee (
+                    id,
+                    fname,
+                    lname,
+                    rsvp,
+                    dietary_requirements,
+                    invitation_opened
+                ) VALUES (
+                    $1::TEXT,
+                    'Test1',
+                    '1',
+                    'Unknown',
+                    'something',
+                    false
+                );
+                ",
+                &[&id],
+            )
+            .await
+            .expect("Insert query should not fail");
+
+        // test
+        let mut db = DB { client };
+        let tx = db.begin().await.expect("Should begin transaction");
+
+        let primary = UpdateInviteeParams {
+            id: id.clone(),
+            rsvp: Rsvp::Coming,
+            dietary_requirements: "Something new".to_string(),
+            dietary: None,
+        };
+        tx.update_invitee(&primary)
+            .await
+            .expect("Should update primary");
+
+        let dependent = UpdateInviteeParams {
+            id: missing_id.clone(),
+            rsvp: Rsvp::Coming,
+            dietary_requirements: "".to_string(),
+            dietary: None,
+        };
+        let dependent = tx.update_invitee(&dependent).await;
+        assert!(matches!(dependent, Err(RepoErr::ItemNotFound(_))));
+
+        tx.rollback().await.expect("Should rollback");
+
+        let invite = db
+            .get_invitee_by_id(&id)
+            .await
+            .expect("Should retrieve invite");
+
+        assert_eq!(invite.rsvp, Rsvp::Unknown);
+        assert_eq!(invite.dietary_requirements, "something".to_owned());
+
+        //cleanup
+        db.client
             .query("DELETE FROM invitee WHERE invitee.id = $1::TEXT", &[&id])
             .await
             .expect("Should delete created");
     }
+
+    #[tokio::test]
+    async fn should_open_invitation() {
+        let client = get_pg_client().await;
+        let id: String = Uuid::new_v4().to_string();
+        let id2: String = Uuid::new_v4().to_string();
+        let id3: String = Uuid::new_v4().to_string();
+
+        // setup
+        client
+            .query(
+                "
+                INSERT INTO invitee (
+                    id,
+                    fname,
+                    lname,
+                    rsvp,
+                    dietary_requirements,
+                    invitation_opened
+                ) SELECT unnest($1::TEXT[]), 'Test1', '1', 'Unknown', 'something', false;
+                ",
+                &[&vec![&id, &id2, &id3]],
+            )
+            .await
+            .expect("Insert query should not fail");
+        client
+            .query(
+                "INSERT INTO relation (parent, child) VALUES ($1::TEXT, $2::TEXT), ($1::TEXT, $3::TEXT)",
+                &[&id, &id2, &id3],
+            )
+            .await
+            .expect("Insert query should not fail");
+
+        // test
+        let db = DB { client };
+        let invitation = db
+            .open_invitation(&id)
+            .await
+            .expect("Should open invitation");
+
+        assert_eq!(invitation.primary_invitee.id, id);
+        assert_eq!(invitation.dependents.len(), 2);
+
+        let opened = db
+            .client
+            .query(
+                "SELECT id FROM invitee WHERE invitation_opened AND id IN (SELECT unnest($1::TEXT[]))",
+                &[&vec![&id, &id2, &id3]],
+            )
+            .await
+            .expect("Should query opened");
+        assert_eq!(opened.len(), 3);
+
+        let missing = db.open_invitation(&Uuid::new_v4().to_string()).await;
+        assert!(matches!(missing, Err(RepoErr::ItemNotFound(_))));
+
+        //cleanup
+        db.client
+            .query(
+                "DELETE FROM invitee WHERE invitee.id IN (SELECT unnest($1::TEXT[]))",
+                &[&vec![id, id2, id3]],
+            )
+            .await
+            .expect("Should delete created");
+    }
+
+    #[tokio::test]
+    async fn should_get_invitation_without_opening() {
+        let client = get_pg_client().await;
+        let id: String = Uuid::new_v4().to_string();
+        let id2: String = Uuid::new_v4().to_string();
+
+        // setup
+        client
+            .query(
+                "
+                INSERT INTO invitee (
+                    id,
+                    fname,
+                    lname,
+                    rsvp,
+                    dietary_requirements,
+                    invitation_opened
+                ) SELECT unnest($1::TEXT[]), 'Test1', '1', 'Unknown', 'something', false;
+                ",
+                &[&vec![&id, &id2]],
+            )
+            .await
+            .expect("Insert query should not fail");
+        client
+            .query(
+                "INSERT INTO relation (parent, child) VALUES ($1::TEXT, $2::TEXT)",
+                &[&id, &id2],
+            )
+            .await
+            .expect("Insert query should not fail");
+
+        // test
+        let db = DB { client };
+        let invitation = db.get_invitation(&id).await.expect("Should get invitation");
+        db.get_invitee_by_id(&id2)
+            .await
+            .expect("Should retrieve invite");
+
+        assert_eq!(invitation.primary_invitee.id, id);
+        assert_eq!(invitation.dependents.len(), 1);
+
+        let opened_query =
+            "SELECT id FROM invitee WHERE invitation_opened AND id IN (SELECT unnest($1::TEXT[]))";
+        let opened = db
+            .client
+            .query(opened_query, &[&vec![&id, &id2]])
+            .await
+            .expect("Should query opened");
+        assert_eq!(opened.len(), 0);
+
+        db.mark_opened(&[&id2]).await.expect("Should mark opened");
+
+        let opened = db
+            .client
+            .query(opened_query, &[&vec![&id, &id2]])
+            .await
+            .expect("Should query opened");
+        assert_eq!(opened.len(), 1);
+
+        //cleanup
+        db.client
+            .query(
+                "DELETE FROM invitee WHERE invitee.id IN (SELECT unnest($1::TEXT[]))",
+                &[&vec![id, id2]],
+            )
+            .await
+            .expect("Should delete created");
+    }
+
+    #[tokio::test]
+    async fn should_create_edit_and_delete_invitee() {
+        let client = get_pg_client().await;
+        let db = DB { client };
+        let id: String = Uuid::new_v4().to_string();
+
+        let mut invitee = InviteeDTO {
+            id: id.clone(),
+            fname: "Test1".to_string(),
+            lname: "1".to_string(),
+            rsvp: Rsvp::Unknown,
+            dietary_requirements: "".to_string(),
+            dietary: Some(DietaryProfile::default()),
+        };
+
+        let created = db
+            .create_invitee(&invitee)
+            .await
+            .expect("Should create invitee");
+        assert_eq!(created, invitee);
+
+        invitee.fname = "Test2".to_string();
+        invitee.rsvp = Rsvp::Maybe;
+        let edited = db
+            .edit_invitee(&invitee)
+            .await
+            .expect("Should edit invitee");
+        assert_eq!(edited, invitee);
+        assert_eq!(db.get_invitee_by_id(&id).await.unwrap(), invitee);
+
+        db.delete_invitee(&id).await.expect("Should delete invitee");
+        let missing = db.get_invitee_by_id(&id).await;
+        assert!(matches!(missing, Err(RepoErr::ItemNotFound(_))));
+        let missing = db.delete_invitee(&id).await;
+        assert!(matches!(missing, Err(RepoErr::ItemNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn should_count_rsvps_by_household_and_tag() {
+        let client = get_pg_client().await;
+        let db = DB { client };
+        let id = insert_test_invitee(&db, Rsvp::Coming).await;
+        let id2 = insert_test_invitee(&db, Rsvp::Unknown).await;
+        let tag: String = Uuid::new_v4().to_string();
+
+        for id in [&id, &id2] {
+            db.set_tags(id, std::slice::from_ref(&tag))
+                .await
+                .expect("Should set tags");
+        }
+        db.add_relation(&id, &id2)
+            .await
+            .expect("Should add relation");
+        db.set_email(&id, Some("test@example.com"))
+            .await
+            .expect("Should set email");
+        db.mark_opened(&[&id2]).await.expect("Should mark opened");
+
+        let guests = db.get_guest_list().await.expect("Should get guest list");
+        let guest = guests.iter().find(|e| e.invitee.id == id2).unwrap();
+        assert_eq!(guest.parent, Some(id.clone()));
+        assert_eq!(guest.tags, vec![tag.clone()]);
+        assert!(guest.invitation_opened);
+
+        let stats = db.get_stats().await.expect("Should get stats");
+        let expected = RsvpCounts {
+            total: 2,
+            coming: 1,
+            unanswered: 1,
+            opened_unanswered: 1,
+            ..Default::default()
+        };
+        let household = stats.households.iter().find(|e| e.household == id).unwrap();
+        assert_eq!(household.counts, expected);
+        let tagged = stats.tags.iter().find(|e| e.tag == tag).unwrap();
+        assert_eq!(tagged.counts, expected);
+
+        //cleanup
+        db.client
+            .query(
+                "DELETE FROM invitee WHERE invitee.id IN (SELECT unnest($1::TEXT[]))",
+                &[&vec![id, id2]],
+            )
+            .await
+            .expect("Should delete created");
+    }
+
+    #[tokio::test]
+    async fn should_mark_invite_sent() {
+        let client = get_pg_client().await;
+        let db = DB { client };
+        let id = insert_test_invitee(&db, Rsvp::Unknown).await;
+        db.set_email(&id, Some("test@example.com"))
+            .await
+            .expect("Should set email");
+
+        let unsent = db.get_unsent_emails().await.expect("Should get emails");
+        let email = unsent.iter().find(|e| e.invitee == id).unwrap();
+        assert_eq!(email.household, id);
+        assert_eq!(email.email, "test@example.com");
+
+        db.mark_invite_sent(&id).await.expect("Should mark sent");
+        let unsent = db.get_unsent_emails().await.expect("Should get emails");
+        assert!(!unsent.iter().any(|e| e.invitee == id));
+
+        //cleanup
+        db.delete_invitee(&id).await.expect("Should delete created");
+    }
+
+    async fn outbox_status(db: &DB, id: &str) -> String {
+        let row = db
+            .client
+            .query_one("SELECT status FROM outbox WHERE id = $1::TEXT", &[&id])
+            .await
+            .expect("Should find message");
+        row.get(0)
+    }
+
+    #[tokio::test]
+    async fn should_deliver_queued_confirmation() {
+        let client = get_pg_client().await;
+        let db = DB { client };
+        let id = insert_test_invitee(&db, Rsvp::Coming).await;
+        let invitee = db.get_invitee_by_id(&id).await.expect("Should get invitee");
+        db.set_email(&id, Some("test@example.com"))
+            .await
+            .expect("Should set email");
+        let email = db.get_household_email(&id).await.expect("Should get email");
+        assert_eq!(email.as_deref(), Some("test@example.com"));
+
+        let message = OutboxMessage {
+            id: Uuid::new_v4().to_string(),
+            template: Template::Confirmation,
+            to: "test@example.com".to_string(),
+            invitation: InvitationATO {
+                primary_invitee: invitee,
+                dependents: vec![],
+            },
+            attempts: 0,
+        };
+        db.enqueue(&message).await.expect("Should enqueue");
+
+        let claimed = db
+            .claim_pending(100, Duration::from_secs(60))
+            .await
+            .expect("Should claim messages");
+        let claimed = claimed.iter().find(|e| e.id == message.id);
+        assert_eq!(claimed.map(|e| e.attempts), Some(1));
+        assert_eq!(claimed.map(|e| &e.invitation), Some(&message.invitation));
+
+        // Leased to this worker, until the failed attempt makes it due again
+        let claimed = db
+            .claim_pending(100, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert!(!claimed.iter().any(|e| e.id == message.id));
+        db.mark_failed(&message.id, "Mailbox unavailable", Some(Duration::ZERO))
+            .await
+            .expect("Should mark failed");
+        let claimed = db
+            .claim_pending(100, Duration::from_secs(60))
+            .await
+            .unwrap();
+        let claimed = claimed.iter().find(|e| e.id == message.id);
+        assert_eq!(claimed.map(|e| e.attempts), Some(2));
+
+        db.mark_failed(&message.id, "Mailbox unavailable", None)
+            .await
+            .expect("Should dead-letter");
+        assert_eq!(outbox_status(&db, &message.id).await, "dead");
+        db.mark_delivered(&message.id)
+            .await
+            .expect("Should mark delivered");
+        assert_eq!(outbox_status(&db, &message.id).await, "delivered");
+
+        //cleanup
+        db.client
+            .execute("DELETE FROM outbox WHERE id = $1::TEXT", &[&message.id])
+            .await
+            .expect("Should delete message");
+        db.delete_invitee(&id).await.expect("Should delete created");
+    }
+
+    #[tokio::test]
+    async fn should_find_households_to_remind() {
+        let client = get_pg_client().await;
+        let db = DB { client };
+        let id = insert_test_invitee(&db, Rsvp::Unknown).await;
+        db.set_email(&id, Some("test@example.com"))
+            .await
+            .expect("Should set email");
+
+        // Not invited yet
+        let candidates = db
+            .get_reminder_candidates()
+            .await
+            .expect("Should get candidates");
+        assert!(!candidates.iter().any(|e| e.household == id));
+
+        db.mark_invite_sent(&id).await.expect("Should mark sent");
+        db.record_reminder(&id, ReminderSegment::Unopened)
+            .await
+            .expect("Should record reminder");
+        db.set_reminder_settings(&ReminderSettings {
+            household: id.clone(),
+            cadence_days: Some(2),
+            max_reminders: None,
+        })
+        .await
+        .expect("Should set settings");
+
+        let candidates = db
+            .get_reminder_candidates()
+            .await
+            .expect("Should get candidates");
+        let candidate = candidates
+            .iter()
+            .find(|e| e.household == id)
+            .expect("Should be a candidate");
+        assert_eq!(candidate.email, "test@example.com");
+        assert!(!candidate.opened);
+        assert!(candidate.invited_at.is_some());
+        assert_eq!(candidate.reminders_sent, 1);
+        assert!(candidate.last_reminded_at.is_some());
+        assert_eq!(candidate.settings.cadence_days, Some(2));
+
+        //cleanup
+        db.delete_invitee(&id).await.expect("Should delete created");
+    }
+
+    #[tokio::test]
+    async fn locked_reminder_candidate_should_wait_for_other_run() {
+        let db = DB {
+            client: get_pg_client().await,
+        };
+        let id = insert_test_invitee(&db, Rsvp::Unknown).await;
+        db.set_email(&id, Some("test@example.com"))
+            .await
+            .expect("Should set email");
+        db.mark_invite_sent(&id).await.expect("Should mark sent");
+
+        let mut first = DB {
+            client: get_pg_client().await,
+        };
+        let tx = first.begin().await.expect("Should begin transaction");
+        let locked = tx.lock_reminder_candidate(&id).await;
+
+        let household = id.clone();
+        let second = tokio::spawn(async move {
+            let mut second = DB {
+                client: get_pg_client().await,
+            };
+            let tx = second.begin().await.expect("Should begin transaction");
+            let candidate = tx.lock_reminder_candidate(&household).await;
+            tx.rollback().await.expect("Should roll back");
+            candidate
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let waited = !second.is_finished();
+        tx.record_reminder(&id, ReminderSegment::Opened)
+            .await
+            .expect("Should record reminder");
+        tx.commit().await.expect("Should commit");
+        let second = second.await.expect("Should join");
+
+        //cleanup
+        db.delete_invitee(&id).await.expect("Should delete created");
+
+        let locked = locked.expect("Should lock candidate");
+        assert_eq!(locked.map(|e| e.reminders_sent), Some(0));
+        assert!(waited);
+        let second = second.expect("Should lock candidate");
+        assert_eq!(second.map(|e| e.reminders_sent), Some(1));
+    }
+
+    #[tokio::test]
+    async fn should_set_and_clear_rsvp_deadline() {
+        let client = get_pg_client().await;
+        let db = DB { client };
+        let id = insert_test_invitee(&db, Rsvp::Unknown).await;
+        let deadline: DateTime<Utc> = "2027-03-01T00:00:00Z".parse().unwrap();
+
+        db.set_rsvp_deadline(&id, Some(deadline))
+            .await
+            .expect("Should set deadline");
+        let result = db.get_rsvp_deadline(&id).await;
+        db.set_rsvp_deadline(&id, None)
+            .await
+            .expect("Should clear deadline");
+        let cleared = db.get_rsvp_deadline(&id).await;
+
+        //cleanup
+        db.delete_invitee(&id).await.expect("Should delete created");
+
+        assert_eq!(result.expect("Should get deadline"), Some(deadline));
+        assert_eq!(cleared.expect("Should get deadline"), None);
+    }
+
+    #[tokio::test]
+    async fn should_issue_and_revoke_token() {
+        let client = get_pg_client().await;
+        let db = DB { client };
+        let id = insert_test_invitee(&db, Rsvp::Unknown).await;
+
+        let first = db
+            .get_or_issue_token(&id, &Uuid::new_v4().to_string())
+            .await;
+        let again = db
+            .get_or_issue_token(&id, &Uuid::new_v4().to_string())
+            .await;
+        let household = db
+            .get_token_household(first.as_deref().unwrap_or_default())
+            .await;
+        let active = db.get_token(&id).await;
+        let revoked = db.revoke_tokens(&id).await;
+        let revoked_active = db.get_token(&id).await;
+        let after = db
+            .get_token_household(first.as_deref().unwrap_or_default())
+            .await;
+
+        //cleanup
+        db.delete_invitee(&id).await.expect("Should delete created");
+
+        let first = first.expect("Should issue token");
+        assert_eq!(again.expect("Should get token"), first);
+        assert_eq!(household.expect("Should find household"), Some(id));
+        assert_eq!(active.expect("Should get token"), Some(first.clone()));
+        assert_eq!(revoked.expect("Should revoke"), 1);
+        assert_eq!(revoked_active.expect("Should get token"), None);
+        assert_eq!(after.expect("Should look up token"), None);
+    }
 }
@@ -0,0 +1,182 @@
+use super::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+
+/// A household's own rsvp deadline, `None` falling back to the global one
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RsvpDeadline {
+    pub household: String,
+    #[serde(default)]
+    pub deadline: Option<DateTime<Utc>>,
+}
+
+/// The household's own deadline, otherwise the global one
+pub async fn household_deadline<T: DeadlineRepo>(
+    household: &str,
+    global: Option<DateTime<Utc>>,
+    db: &T,
+) -> Result<Option<DateTime<Utc>>, ApiErr> {
+    let deadline = db
+        .get_rsvp_deadline(household)
+        .await
+        .map_err(ApiErr::RepoErr);
+
+    if let Err(err) = deadline {
+        event!(Level::ERROR, "Failed to get rsvp deadline");
+        return Err(err);
+    }
+
+    Ok(deadline.expect("Should handle err").or(global))
+}
+
+/// The household's deadline as it is written in emails, such as "1 March 2027", by its date in UTC
+pub async fn deadline_text<T: DeadlineRepo>(
+    household: &str,
+    global: Option<DateTime<Utc>>,
+    db: &T,
+) -> Result<Option<String>, ApiErr> {
+    let deadline = household_deadline(household, global, db).await?;
+
+    Ok(deadline.map(|e| e.format("%-d %B %Y").to_string()))
+}
+
+/// Refuses changes to the household's answers once their deadline has passed, otherwise
+/// returning the deadline
+pub async fn check_deadline<T: DeadlineRepo>(
+    household: &str,
+    global: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    db: &T,
+) -> Result<Option<DateTime<Utc>>, ApiErr> {
+    match household_deadline(household, global, db).await? {
+        Some(deadline) if deadline <= now => {
+            event!(
+                Level::WARN,
+                msg = "Attempted to rsvp after the deadline",
+                household
+            );
+            Err(ApiErr::RsvpClosed(deadline))
+        }
+        deadline => Ok(deadline),
+    }
+}
+
+/// Overrides a household's rsvp deadline, `None` restoring the global one
+#[tracing::instrument(skip(db))]
+pub async fn set_rsvp_deadline<T: InviteeRepo + RelationRepo + DeadlineRepo>(
+    deadline: &RsvpDeadline,
+    db: &T,
+) -> Result<RsvpDeadline, ApiErr> {
+    ensure_primary(&deadline.household, db).await?;
+
+    if let Err(err) = db
+        .set_rsvp_deadline(&deadline.household, deadline.deadline)
+        .await
+    {
+        event!(Level::ERROR, "Failed to set rsvp deadline");
+        return Err(ApiErr::RepoErr(err));
+    }
+
+    Ok(deadline.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+    const SINGLE: &str = "e01601bb-0647-410f-91b5-ca4df097c175";
+
+    async fn answer(db: &mut MemoryRepo, global: Option<DateTime<Utc>>) -> Result<(), ApiErr> {
+        let token = household_token(PRIMARY, db).await.unwrap();
+        let invitation = db.get_invitation(PRIMARY).await.unwrap();
+        let mut answers = GuestInvitation::new(&invitation, None);
+        answers.primary_invitee.rsvp = Rsvp::Coming;
+        update_invitation(&token, &answers, global, db)
+            .await
+            .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn should_lock_answers_after_global_deadline() {
+        let mut db = MemoryRepo::seeded();
+        let passed = Utc::now() - Duration::days(1);
+
+        let result = answer(&mut db, Some(passed)).await;
+
+        assert!(matches!(result, Err(ApiErr::RsvpClosed(e)) if e == passed));
+        let primary = db.get_invitee_by_id(PRIMARY).await.unwrap();
+        assert_eq!(primary.rsvp, Rsvp::Unknown);
+        assert!(answer(&mut db, Some(Utc::now() + Duration::days(1)))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn household_deadline_should_override_global() {
+        let mut db = MemoryRepo::seeded();
+        let extended = Utc::now() + Duration::days(7);
+        set_rsvp_deadline(
+            &RsvpDeadline {
+                household: PRIMARY.to_string(),
+                deadline: Some(extended),
+            },
+            &db,
+        )
+        .await
+        .expect("Should set deadline");
+
+        answer(&mut db, Some(Utc::now() - Duration::days(1)))
+            .await
+            .expect("Should answer before the household's deadline");
+
+        let token = household_token(PRIMARY, &db).await.unwrap();
+        let invitation = fetch_invitation(&token, None, &db).await.unwrap();
+        assert_eq!(invitation.deadline, Some(extended));
+        let token = household_token(SINGLE, &db).await.unwrap();
+        let invitation = fetch_invitation(&token, Some(extended), &db).await.unwrap();
+        assert_eq!(invitation.deadline, Some(extended));
+    }
+
+    #[tokio::test]
+    async fn admin_should_bypass_deadline() {
+        let mut db = MemoryRepo::seeded();
+        set_rsvp_deadline(
+            &RsvpDeadline {
+                household: PRIMARY.to_string(),
+                deadline: Some(Utc::now() - Duration::days(1)),
+            },
+            &db,
+        )
+        .await
+        .unwrap();
+        let mut invitation = db.get_invitation(PRIMARY).await.unwrap();
+        invitation.dependents[0].rsvp = Rsvp::NotComing;
+
+        let invitation = override_invitation(&invitation, &mut db)
+            .await
+            .expect("Should save after the deadline");
+
+        assert_eq!(invitation.dependents[0].rsvp, Rsvp::NotComing);
+    }
+
+    #[tokio::test]
+    async fn deadline_should_be_for_primary_invitee() {
+        let db = MemoryRepo::seeded();
+
+        let result = set_rsvp_deadline(
+            &RsvpDeadline {
+                household: DEPENDENT.to_string(),
+                deadline: Some(Utc::now()),
+            },
+            &db,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiErr::HouseholdConflict(_))));
+    }
+}
@@ -0,0 +1,14 @@
+use super::*;
+
+/// Runs the job the function was invoked with, e.g. `aws lambda invoke --payload
+/// '{"job":"deliverOutbox"}'`, failing the invocation when the job does
+#[tracing::instrument(skip_all)]
+pub async fn handle_direct(event: Value, app: Result<&App, &ConfigErr>) -> Result<Value, StdErr> {
+    event!(Level::INFO, "Direct invocation");
+
+    let job: Job = serde_json::from_value(event)
+        .map_err(|err| format!("Could not parse into a job: {}", err))?;
+    let app = app.map_err(|err| err.to_string())?;
+
+    run_job(job, app).await
+}
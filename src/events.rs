@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A background job, run by invoking the function directly or sending it to its SQS queue,
+/// e.g. `{"job":"sendReminders"}`
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(tag = "job", rename_all = "camelCase")]
+pub enum Job {
+    DeliverOutbox,
+    SendReminders,
+    /// Imports a guest list csv, only writing the changes given `apply`
+    ImportGuests {
+        csv: String,
+        #[serde(default)]
+        apply: bool,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SqsMessage {
+    pub message_id: String,
+    pub body: String,
+}
+
+/// The kinds of event the function is invoked with
+#[derive(Debug, PartialEq, Eq)]
+pub enum Invocation {
+    /// An EventBridge schedule
+    Scheduled,
+    /// A batch of messages from an SQS queue, each holding a [`Job`]
+    Sqs(Vec<SqsMessage>),
+    /// The function invoked with a [`Job`]
+    Direct,
+    /// An API Gateway proxy event, which anything unrecognised is also treated as
+    ApiGateway,
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+impl Invocation {
+    pub fn of(event: &Value) -> Self {
+        if field(event, "source") == Some("aws.events")
+            && field(event, "detail-type") == Some("Scheduled Event")
+        {
+            return Invocation::Scheduled;
+        }
+
+        if let Some(records) = event.get("Records").and_then(Value::as_array) {
+            if !records.is_empty()
+                && records
+                    .iter()
+                    .all(|e| field(e, "eventSource") == Some("aws:sqs"))
+            {
+                return Invocation::Sqs(
+                    records
+                        .iter()
+                        .map(|e| SqsMessage {
+                            message_id: field(e, "messageId").unwrap_or_default().to_string(),
+                            body: field(e, "body").unwrap_or_default().to_string(),
+                        })
+                        .collect(),
+                );
+            }
+        }
+
+        if event.get("job").is_some() {
+            return Invocation::Direct;
+        }
+
+        Invocation::ApiGateway
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_recognise_events() {
+        let scheduled = json!({
+            "source": "aws.events",
+            "detail-type": "Scheduled Event",
+            "detail": {}
+        });
+        let sqs = json!({
+            "Records": [{
+                "messageId": "1",
+                "eventSource": "aws:sqs",
+                "body": "{\"job\":\"deliverOutbox\"}"
+            }]
+        });
+        let direct = json!({ "job": "sendReminders" });
+        let api = json!({ "body": "{}", "headers": {}, "requestContext": {} });
+
+        assert_eq!(Invocation::of(&scheduled), Invocation::Scheduled);
+        assert_eq!(
+            Invocation::of(&sqs),
+            Invocation::Sqs(vec![SqsMessage {
+                message_id: "1".to_string(),
+                body: "{\"job\":\"deliverOutbox\"}".to_string(),
+            }])
+        );
+        assert_eq!(Invocation::of(&direct), Invocation::Direct);
+        assert_eq!(Invocation::of(&api), Invocation::ApiGateway);
+    }
+
+    #[test]
+    fn job_should_deserialize() {
+        let job: Job = serde_json::from_value(json!({
+            "job": "importGuests",
+            "csv": "fname,lname,household,email,tags\n"
+        }))
+        .expect("should parse properly");
+
+        assert_eq!(
+            job,
+            Job::ImportGuests {
+                csv: "fname,lname,household,email,tags\n".to_string(),
+                apply: false,
+            }
+        );
+    }
+}
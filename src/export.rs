@@ -0,0 +1,225 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+use tracing::{event, Level};
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// One invitee of the exported guest list, `household` being the id of their primary invitee
+#[derive(Serialize, Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(rename_all = "camelCase")]
+pub struct GuestExportRow {
+    pub household: String,
+    pub id: String,
+    pub fname: String,
+    pub lname: String,
+    pub email: Option<String>,
+    pub tags: Vec<String>,
+    pub rsvp: String,
+    pub dietary_requirements: String,
+    pub dietary: DietaryProfile,
+    pub invitation_opened: bool,
+}
+
+impl GuestExportRow {
+    fn new(household: &str, entry: &GuestListEntry) -> Self {
+        Self {
+            household: household.to_string(),
+            id: entry.invitee.id.clone(),
+            fname: entry.invitee.fname.clone(),
+            lname: entry.invitee.lname.clone(),
+            email: entry.email.clone(),
+            tags: entry.tags.clone(),
+            rsvp: entry.invitee.rsvp.to_string(),
+            dietary_requirements: entry.invitee.dietary_requirements.clone(),
+            dietary: entry.invitee.dietary.clone().unwrap_or_default(),
+            invitation_opened: entry.invitation_opened,
+        }
+    }
+}
+
+/// Lists every invitee household by household, each primary invitee followed by their dependents
+#[tracing::instrument(skip(db))]
+pub async fn export_guest_list<T: RelationRepo + GuestListRepo>(
+    db: &T,
+) -> Result<Vec<GuestExportRow>, ApiErr> {
+    let guests = db.get_guest_list().await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = guests {
+        event!(Level::ERROR, "Failed to get guest list");
+        return Err(err);
+    }
+    let guests = guests.expect("Should handle err");
+
+    let mut rows = vec![];
+    for primary in guests.iter().filter(|e| e.parent.is_none()) {
+        let household = &primary.invitee.id;
+        rows.push(GuestExportRow::new(household, primary));
+
+        let dependents = db.get_dependents(household).await.map_err(ApiErr::RepoErr);
+
+        if let Err(err) = dependents {
+            event!(Level::ERROR, "Failed to find dependents");
+            return Err(err);
+        }
+
+        // Dependents keep the guest list's order, rather than the order they were added
+        let dependents = dependents.expect("Should handle err");
+        for dependent in guests.iter().filter(|e| dependents.contains(&e.invitee.id)) {
+            rows.push(GuestExportRow::new(household, dependent));
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Allergies as they are written in one csv field, "peanut (Severe);shellfish (Mild)"
+fn allergies_csv(profile: &DietaryProfile) -> String {
+    profile
+        .allergies
+        .iter()
+        .map(|e| format!("{} ({})", e.allergen.trim(), e.severity.as_str()))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+/// Writes the exported guest list as csv, which can be imported again
+pub fn guest_list_csv(rows: &[GuestExportRow]) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    writer
+        .write_record([
+            "household",
+            "id",
+            "fname",
+            "lname",
+            "email",
+            "tags",
+            "rsvp",
+            "dietary_requirements",
+            "diet",
+            "allergies",
+            "dietary_notes",
+            "invitation_opened",
+        ])
+        .expect("Writing to memory should not fail");
+
+    for row in rows {
+        writer
+            .write_record([
+                row.household.as_str(),
+                row.id.as_str(),
+                row.fname.as_str(),
+                row.lname.as_str(),
+                row.email.as_deref().unwrap_or(""),
+                row.tags.join(";").as_str(),
+                row.rsvp.as_str(),
+                row.dietary_requirements.as_str(),
+                row.dietary.diet.as_str(),
+                allergies_csv(&row.dietary).as_str(),
+                row.dietary.notes.as_str(),
+                if row.invitation_opened {
+                    "true"
+                } else {
+                    "false"
+                },
+            ])
+            .expect("Writing to memory should not fail");
+    }
+
+    let csv = writer
+        .into_inner()
+        .expect("Writing to memory should not fail");
+    String::from_utf8(csv).expect("Csv should be written from strings")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const SINGLE: &str = "e01601bb-0647-410f-91b5-ca4df097c175";
+
+    #[tokio::test]
+    async fn should_export_every_household() {
+        let db = MemoryRepo::seeded();
+        db.set_tags(PRIMARY, &["family".to_string()]).await.unwrap();
+        db.open_invitation(PRIMARY).await.unwrap();
+
+        let rows = export_guest_list(&db).await.expect("Should export guests");
+
+        let names: Vec<(&str, &str)> = rows
+            .iter()
+            .map(|e| (e.household.as_str(), e.fname.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (PRIMARY, "David"),
+                (PRIMARY, "Mia"),
+                (PRIMARY, "Joseph"),
+                (SINGLE, "Willian"),
+            ]
+        );
+        assert!(rows[1].invitation_opened);
+        assert!(!rows[3].invitation_opened);
+        assert_eq!(rows[3].rsvp, "Unknown");
+    }
+
+    #[tokio::test]
+    async fn should_export_csv_which_imports_unchanged() {
+        let db = MemoryRepo::seeded();
+        db.set_email(PRIMARY, Some("david@example.com"))
+            .await
+            .unwrap();
+        db.set_tags(PRIMARY, &["family".to_string(), "uni".to_string()])
+            .await
+            .unwrap();
+
+        let rows = export_guest_list(&db).await.unwrap();
+        let csv = guest_list_csv(&rows);
+
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("2e53abf4-323a-48ce-9ec2-0c0850c15523,2e53abf4-323a-48ce-9ec2-0c0850c15523,David,Kwong,david@example.com,family;uni,Unknown,,None,,,false")
+        );
+        let records = parse_guest_csv(csv.as_bytes()).unwrap();
+        let changes = preview_guest_import(&records, &db).await.unwrap();
+        assert!(changes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_export_dietary_profile() {
+        let db = MemoryRepo::seeded();
+        let mut primary = db.get_invitee_by_id(PRIMARY).await.unwrap();
+        primary.dietary = Some(DietaryProfile {
+            diet: Diet::Other,
+            allergies: vec![
+                Allergy {
+                    allergen: "Peanut".to_string(),
+                    severity: AllergySeverity::Severe,
+                },
+                Allergy {
+                    allergen: "Shellfish".to_string(),
+                    severity: AllergySeverity::Mild,
+                },
+            ],
+            notes: "Low FODMAP".to_string(),
+        });
+        db.edit_invitee(&primary).await.unwrap();
+
+        let rows = export_guest_list(&db).await.unwrap();
+
+        assert_eq!(rows[0].dietary, primary.dietary.unwrap());
+        assert_eq!(
+            guest_list_csv(&rows).lines().nth(1),
+            Some("2e53abf4-323a-48ce-9ec2-0c0850c15523,2e53abf4-323a-48ce-9ec2-0c0850c15523,David,Kwong,,,Unknown,,Other,Peanut (Severe);Shellfish (Mild),Low FODMAP,false")
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use super::*;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
 use thiserror::Error;
 use tracing::{event, Level};
 
@@ -12,72 +14,406 @@ pub enum RepoErr {
 }
 
 #[async_trait]
-pub trait InviteeRepo {
+pub trait InviteeRepo: Send + Sync {
     async fn get_invitee_by_id(&self, id: &str) -> Result<InviteeDTO, RepoErr>;
-    async fn get_invitee_by_ids(&self, ids: &Vec<&str>) -> Result<Vec<InviteeDTO>, RepoErr>;
+    async fn get_invitee_by_ids(&self, ids: &[&str]) -> Result<Vec<InviteeDTO>, RepoErr>;
     async fn update_invitee(&self, invitee: &UpdateInviteeParams) -> Result<InviteeDTO, RepoErr>;
+    async fn mark_opened(&self, ids: &[&str]) -> Result<(), RepoErr>;
+    async fn create_invitee(&self, invitee: &InviteeDTO) -> Result<InviteeDTO, RepoErr>;
+    /// Overwrites every field of the invitee, including their name
+    async fn edit_invitee(&self, invitee: &InviteeDTO) -> Result<InviteeDTO, RepoErr>;
+    /// Deletes the invitee, along with any relations they are part of
+    async fn delete_invitee(&self, id: &str) -> Result<(), RepoErr>;
+    /// Fetches the primary invitee with their dependents, without any side effects
+    async fn get_invitation(&self, id: &str) -> Result<InvitationATO, RepoErr>;
+
+    /// Fetches the primary invitee with their dependents, marking all of them as opened
+    async fn open_invitation(&self, id: &str) -> Result<InvitationATO, RepoErr> {
+        let invitation = self.get_invitation(id).await?;
+        let ids: Vec<&str> = std::iter::once(&invitation.primary_invitee)
+            .chain(&invitation.dependents)
+            .map(|e| e.id.as_str())
+            .collect();
+        self.mark_opened(&ids).await?;
+        Ok(invitation)
+    }
 }
 
 #[async_trait]
-pub trait RelationRepo {
+pub trait RelationRepo: Send + Sync {
     async fn get_dependents(&self, id: &str) -> Result<Vec<String>, RepoErr>;
+    /// Finds the primary invitee whose household the invitee belongs to, if any
+    async fn get_parent(&self, id: &str) -> Result<Option<String>, RepoErr>;
+    async fn add_relation(&self, parent: &str, child: &str) -> Result<(), RepoErr>;
+    async fn remove_relation(&self, parent: &str, child: &str) -> Result<(), RepoErr>;
+}
+
+/// The whole guest list at once, with the contact details and tags not part of an invitation
+#[async_trait]
+pub trait GuestListRepo: Send + Sync {
+    /// Every invitee, ordered by last then first name
+    async fn get_guest_list(&self) -> Result<Vec<GuestListEntry>, RepoErr>;
+    /// Sets or, given `None`, removes the invitee's email
+    async fn set_email(&self, id: &str, email: Option<&str>) -> Result<(), RepoErr>;
+    /// Replaces all of the invitee's tags
+    async fn set_tags(&self, id: &str, tags: &[String]) -> Result<(), RepoErr>;
+}
+
+#[async_trait]
+pub trait EmailRepo: Send + Sync {
+    /// Emails of invitees who have not yet been sent their invitation, grouped by household with
+    /// the primary invitee's first
+    async fn get_unsent_emails(&self) -> Result<Vec<InviteeEmail>, RepoErr>;
+    async fn mark_invite_sent(&self, invitee: &str) -> Result<(), RepoErr>;
+    /// The address a household is emailed at, the primary invitee's when they have one
+    async fn get_household_email(&self, household: &str) -> Result<Option<String>, RepoErr>;
+}
+
+/// Emails queued alongside the writes they are about, to be delivered once those are committed
+#[async_trait]
+pub trait OutboxRepo: Send + Sync {
+    async fn enqueue(&self, message: &OutboxMessage) -> Result<(), RepoErr>;
+    /// Claims up to `limit` pending messages which are due, counting the attempt and hiding them
+    /// from other workers for `lease`, so a worker which dies mid delivery is retried
+    async fn claim_pending(
+        &self,
+        limit: usize,
+        lease: Duration,
+    ) -> Result<Vec<OutboxMessage>, RepoErr>;
+    async fn mark_delivered(&self, id: &str) -> Result<(), RepoErr>;
+    /// Records a failed attempt, retrying after `retry_in` or, given `None`, dead-lettering it
+    async fn mark_failed(
+        &self,
+        id: &str,
+        error: &str,
+        retry_in: Option<Duration>,
+    ) -> Result<(), RepoErr>;
+}
+
+/// Households to remind about their invitation, and the reminders they have been sent
+#[async_trait]
+pub trait ReminderRepo: Send + Sync {
+    /// Households whose primary invitee has not answered, and who were emailed their invitation
+    async fn get_reminder_candidates(&self) -> Result<Vec<ReminderCandidate>, RepoErr>;
+    /// The household, when still a candidate, locked until the transaction ends so runs of the
+    /// job which overlap remind them once
+    async fn lock_reminder_candidate(
+        &self,
+        household: &str,
+    ) -> Result<Option<ReminderCandidate>, RepoErr>;
+    async fn record_reminder(
+        &self,
+        household: &str,
+        segment: ReminderSegment,
+    ) -> Result<(), RepoErr>;
+    /// Sets the household's own cadence and limit, or removes them when both are `None`
+    async fn set_reminder_settings(&self, settings: &ReminderSettings) -> Result<(), RepoErr>;
+}
+
+#[async_trait]
+pub trait DeadlineRepo: Send + Sync {
+    /// The household's own deadline, without falling back to the global one
+    async fn get_rsvp_deadline(&self, household: &str) -> Result<Option<DateTime<Utc>>, RepoErr>;
+    /// Sets the household's own deadline, or removes it given `None`
+    async fn set_rsvp_deadline(
+        &self,
+        household: &str,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<(), RepoErr>;
+}
+
+#[async_trait]
+pub trait TokenRepo: Send + Sync {
+    /// The household an active token is for
+    async fn get_token_household(&self, token: &str) -> Result<Option<String>, RepoErr>;
+    /// The household's active token, without issuing one
+    async fn get_token(&self, household: &str) -> Result<Option<String>, RepoErr>;
+    /// The household's active token, issuing `token` when they have none
+    async fn get_or_issue_token(&self, household: &str, token: &str) -> Result<String, RepoErr>;
+    /// Revokes the household's active tokens, returning how many there were
+    async fn revoke_tokens(&self, household: &str) -> Result<u64, RepoErr>;
 }
 
+#[async_trait]
+pub trait StatsRepo: Send + Sync {
+    /// Rsvp counts across every invitee, households ordered by name and tags alphabetically
+    async fn get_stats(&self) -> Result<RsvpStats, RepoErr>;
+}
+
+/// A repo which can group several writes into a transaction, so they are applied all-or-nothing
+#[async_trait]
+pub trait UnitOfWork {
+    type Tx<'t>: InviteeRepo
+        + RelationRepo
+        + GuestListRepo
+        + EmailRepo
+        + OutboxRepo
+        + ReminderRepo
+        + DeadlineRepo
+        + TokenRepo
+        + Commit
+    where
+        Self: 't;
+    async fn begin(&mut self) -> Result<Self::Tx<'_>, RepoErr>;
+}
+
+#[async_trait]
+pub trait Commit {
+    async fn commit(self) -> Result<(), RepoErr>;
+    async fn rollback(self) -> Result<(), RepoErr>;
+}
+
+/// Opens the invitation a token is for, with the deadline for the guests' answers given `global`
+/// is the deadline for households without their own
+#[tracing::instrument(skip(token, db))]
+pub async fn fetch_invitation<T: InviteeRepo + RelationRepo + DeadlineRepo + TokenRepo>(
+    token: &str,
+    global: Option<DateTime<Utc>>,
+    db: &T,
+) -> Result<GuestInvitation, ApiErr> {
+    let household = token_household(token, db).await?;
+    let invitation = db
+        .open_invitation(&household)
+        .await
+        .map_err(ApiErr::RepoErr);
+
+    if let Err(err) = invitation {
+        event!(Level::ERROR, "Failed to open invitation");
+        return Err(err);
+    }
+
+    let deadline = household_deadline(&household, global, db).await?;
+    Ok(GuestInvitation::new(
+        &invitation.expect("Should handle err"),
+        deadline,
+    ))
+}
+
+/// Reads an invitation for the couple, leaving the guests' opened status untouched
 #[tracing::instrument(skip(db))]
-pub async fn fetch_invitation<T: InviteeRepo + RelationRepo>(
-    id: &str,
-    db: T,
-) -> Result<InvitationATO, ApiErr> {
-    let dependent_result = db.get_dependents(id).await.map_err(|e| ApiErr::RepoErr(e));
+pub async fn preview_invitation<T: InviteeRepo>(id: &str, db: &T) -> Result<InvitationATO, ApiErr> {
+    let invitation = db.get_invitation(id).await.map_err(ApiErr::RepoErr);
 
-    if let Err(err) = dependent_result {
-        event!(Level::ERROR, "Failed to find dependents");
+    if let Err(err) = invitation {
+        event!(Level::ERROR, "Failed to preview invitation");
         return Err(err);
     }
-    let dependent_result = dependent_result.expect("Should handle err");
 
-    let mut dependents: Option<Vec<InviteeDTO>> = None;
-    if dependent_result.len() > 0 {
-        let invitees = db
-            .get_invitee_by_ids(&dependent_result.iter().map(|e| e.as_str()).collect())
-            .await
-            .map_err(|e| ApiErr::RepoErr(e));
-        if let Err(err) = invitees {
-            event!(Level::ERROR, "Failed to find dependents");
-            return Err(err);
-        }
-        let invitees = invitees.expect("Should handle err");
-        dependents = Some(invitees)
+    invitation
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn get_stats<T: StatsRepo>(db: &T) -> Result<RsvpStats, ApiErr> {
+    let stats = db.get_stats().await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = stats {
+        event!(Level::ERROR, "Failed to get stats");
+        return Err(err);
     }
 
-    let primary_invitee = db
-        .get_invitee_by_id(id)
-        .await
-        .map_err(|e| ApiErr::RepoErr(e));
-    if let Err(err) = primary_invitee {
-        event!(Level::ERROR, "Failed to find primary invitee");
+    stats
+}
+
+/// Saves the answers of the household a token is for, unless their deadline has passed
+#[tracing::instrument(skip(token, db))]
+pub async fn update_invitation<T: UnitOfWork>(
+    token: &str,
+    answers: &GuestInvitation,
+    global: Option<DateTime<Utc>>,
+    db: &mut T,
+) -> Result<GuestInvitation, ApiErr> {
+    let tx = begin_transaction(db).await?;
+    let result = answer_invitation(token, answers, global, &tx).await;
+    finish_transaction(tx, result).await
+}
+
+async fn answer_invitation<
+    T: InviteeRepo + RelationRepo + EmailRepo + OutboxRepo + DeadlineRepo + TokenRepo,
+>(
+    token: &str,
+    answers: &GuestInvitation,
+    global: Option<DateTime<Utc>>,
+    tx: &T,
+) -> Result<GuestInvitation, ApiErr> {
+    let household = token_household(token, tx).await?;
+    let deadline = check_deadline(&household, global, Utc::now(), tx).await?;
+
+    let invitation = tx.get_invitation(&household).await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = invitation {
+        event!(Level::ERROR, "Failed to get invitation");
         return Err(err);
     }
-    let primary_invitee = primary_invitee.expect("Should handle err");
 
-    Ok(InvitationATO {
-        primary_invitee,
-        dependents: dependents.unwrap_or(vec![]),
-    })
+    let answered = answers.answer(&invitation.expect("Should handle err"));
+
+    if let Err(err) = answered {
+        event!(
+            Level::WARN,
+            msg = "Attempted to answer for other guests",
+            household,
+            ?err
+        );
+        return Err(match err {
+            AnswerErr::NotInHousehold(name) => ApiErr::NotInHousehold(name),
+            AnswerErr::Incomplete => ApiErr::ArgumentErr(
+                "The answers are not for every guest in the household".to_string(),
+            ),
+        });
+    }
+
+    let saved = save_invitation(&answered.expect("Should handle err"), tx).await?;
+    Ok(GuestInvitation::new(&saved, deadline))
 }
 
+/// Saves a household's answers regardless of their deadline, for the couple to make late changes
 #[tracing::instrument(skip(db))]
-pub async fn update_invitation<T: InviteeRepo>(
+pub async fn override_invitation<T: UnitOfWork>(
+    invitation: &InvitationATO,
+    db: &mut T,
+) -> Result<InvitationATO, ApiErr> {
+    let tx = begin_transaction(db).await?;
+    let result = save_invitation(invitation, &tx).await;
+    finish_transaction(tx, result).await
+}
+
+async fn save_invitation<T: InviteeRepo + RelationRepo + EmailRepo + OutboxRepo>(
+    invitation: &InvitationATO,
+    tx: &T,
+) -> Result<InvitationATO, ApiErr> {
+    let result = write_invitation(invitation, tx).await;
+
+    // Queued in the same transaction, so the guest is only told about answers which were saved
+    match result {
+        Ok(invitation) => enqueue_confirmation(&invitation, tx)
+            .await
+            .map(|_| invitation),
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn begin_transaction<T: UnitOfWork>(db: &mut T) -> Result<T::Tx<'_>, ApiErr> {
+    let tx = db.begin().await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = tx {
+        event!(Level::ERROR, "Failed to begin transaction");
+        return Err(err);
+    }
+
+    tx
+}
+
+/// Commits the transaction if its writes succeeded, otherwise rolls it back
+pub async fn finish_transaction<C: Commit, R>(
+    tx: C,
+    result: Result<R, ApiErr>,
+) -> Result<R, ApiErr> {
+    if let Err(err) = result {
+        if let Err(rollback_err) = tx.rollback().await {
+            event!(
+                Level::ERROR,
+                msg = "Failed to rollback transaction",
+                ?rollback_err
+            );
+        }
+        return Err(err);
+    }
+
+    if let Err(err) = tx.commit().await {
+        event!(Level::ERROR, "Failed to commit transaction");
+        return Err(ApiErr::RepoErr(err));
+    }
+
+    result
+}
+
+const MAX_ALLERGIES: usize = 20;
+const MAX_DIETARY_TEXT: usize = 500;
+
+/// Checks a dietary profile is specific enough for the caterer to act on
+pub fn validate_dietary(profile: &DietaryProfile) -> Result<(), ApiErr> {
+    if profile.diet == Diet::Other && profile.notes.trim().is_empty() {
+        return Err(ApiErr::ArgumentErr(
+            "A diet of other must be described in the notes".to_string(),
+        ));
+    }
+    if profile.notes.chars().count() > MAX_DIETARY_TEXT {
+        return Err(ApiErr::ArgumentErr(format!(
+            "Dietary notes must be at most {} characters",
+            MAX_DIETARY_TEXT
+        )));
+    }
+    if profile.allergies.len() > MAX_ALLERGIES {
+        return Err(ApiErr::ArgumentErr(format!(
+            "At most {} allergies can be listed",
+            MAX_ALLERGIES
+        )));
+    }
+
+    let mut allergens: Vec<String> = vec![];
+    for allergy in &profile.allergies {
+        let allergen = allergy.allergen.trim().to_lowercase();
+        if allergen.is_empty() || allergen.chars().count() > MAX_DIETARY_TEXT {
+            return Err(ApiErr::ArgumentErr(
+                "Every allergy must name its allergen".to_string(),
+            ));
+        }
+        if allergens.contains(&allergen) {
+            return Err(ApiErr::ArgumentErr(format!(
+                "{} is listed more than once",
+                allergy.allergen
+            )));
+        }
+        allergens.push(allergen);
+    }
+    Ok(())
+}
+
+/// Writes the primary invitee and their dependents, stopping at the first failure
+async fn write_invitation<T: InviteeRepo + RelationRepo>(
     invitation: &InvitationATO,
-    db: T,
+    db: &T,
 ) -> Result<InvitationATO, ApiErr> {
+    let household = db
+        .get_dependents(&invitation.primary_invitee.id)
+        .await
+        .map_err(ApiErr::RepoErr);
+
+    if let Err(err) = household {
+        event!(Level::ERROR, "Failed to find dependents");
+        return Err(err);
+    }
+    let household = household.expect("Should handle err");
+
+    // Only members of the primary invitee's household may be updated through their invitation
+    let outsider = invitation
+        .dependents
+        .iter()
+        .find(|e| !household.contains(&e.id));
+
+    if let Some(invitee) = outsider {
+        event!(
+            Level::WARN,
+            msg = "Attempted to update invitee outside of household",
+            ?invitee
+        );
+        return Err(ApiErr::NotInHousehold(invitee.id.clone()));
+    }
+
+    let invalid = std::iter::once(&invitation.primary_invitee)
+        .chain(&invitation.dependents)
+        .filter_map(|e| e.dietary.as_ref())
+        .find_map(|e| validate_dietary(e).err());
+
+    if let Some(err) = invalid {
+        event!(Level::WARN, "Attempted to update invalid dietary profile");
+        return Err(err);
+    }
+
     let mut result = invitation.clone();
     let primary = UpdateInviteeParams::from(&invitation.primary_invitee);
 
-    let primary = db
-        .update_invitee(&primary)
-        .await
-        .map_err(|e| ApiErr::RepoErr(e));
+    let primary = db.update_invitee(&primary).await.map_err(ApiErr::RepoErr);
 
     if let Err(err) = primary {
         event!(Level::ERROR, "Failed to update primary invitee");
@@ -87,15 +423,12 @@ pub async fn update_invitation<T: InviteeRepo>(
 
     result.primary_invitee = primary;
 
-    if invitation.dependents.len() > 0 {
+    if !invitation.dependents.is_empty() {
         let mut dependents_result: Vec<InviteeDTO> = vec![];
 
         for invitee in &invitation.dependents {
             let param = UpdateInviteeParams::from(invitee);
-            let dependent_result = db
-                .update_invitee(&param)
-                .await
-                .map_err(|e| ApiErr::RepoErr(e));
+            let dependent_result = db.update_invitee(&param).await.map_err(ApiErr::RepoErr);
             if let Err(err) = dependent_result {
                 event!(
                     Level::ERROR,
@@ -113,3 +446,227 @@ pub async fn update_invitation<T: InviteeRepo>(
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+    const OUTSIDER: &str = "e01601bb-0647-410f-91b5-ca4df097c175";
+
+    fn coming(mut invitee: InviteeDTO) -> InviteeDTO {
+        invitee.rsvp = Rsvp::Coming;
+        invitee.dietary_requirements = "Vegetarian".to_string();
+        invitee
+    }
+
+    /// The primary invitee's token, and their invitation as the guests see it
+    async fn guest_invitation(db: &MemoryRepo) -> (String, GuestInvitation) {
+        let token = household_token(PRIMARY, db).await.unwrap();
+        let invitation = db.get_invitation(PRIMARY).await.unwrap();
+        (token, GuestInvitation::new(&invitation, None))
+    }
+
+    #[tokio::test]
+    async fn should_count_rsvps() {
+        let db = MemoryRepo::seeded();
+        db.update_invitee(&UpdateInviteeParams::from(&coming(
+            db.get_invitee_by_id(DEPENDENT).await.unwrap(),
+        )))
+        .await
+        .unwrap();
+        db.mark_opened(&[PRIMARY]).await.unwrap();
+
+        let stats = get_stats(&db).await.expect("Should get stats");
+
+        assert_eq!(
+            stats.overall,
+            RsvpCounts {
+                total: 4,
+                coming: 1,
+                unanswered: 3,
+                opened_unanswered: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(stats.households.len(), 2);
+        assert_eq!(stats.households[0].household, PRIMARY);
+        assert_eq!(stats.households[0].counts.total, 3);
+        assert_eq!(stats.households[1].counts.total, 1);
+    }
+
+    #[tokio::test]
+    async fn should_fetch_and_open_invitation() {
+        let db = MemoryRepo::seeded();
+        let token = household_token(PRIMARY, &db).await.unwrap();
+
+        let invitation = fetch_invitation(&token, None, &db)
+            .await
+            .expect("Should fetch invitation");
+
+        assert_eq!(invitation.primary_invitee.fname, "David");
+        assert_eq!(invitation.dependents[0].fname, "Joseph");
+        assert_eq!(invitation.dependents[1].fname, "Mia");
+        assert_eq!(invitation.deadline, None);
+        let json = serde_json::to_string(&invitation).unwrap();
+        assert!(!json.contains(PRIMARY) && !json.contains(DEPENDENT));
+        assert_eq!(db.is_opened(PRIMARY), Some(true));
+        assert_eq!(db.is_opened(DEPENDENT), Some(true));
+        assert_eq!(db.is_opened(OUTSIDER), Some(false));
+    }
+
+    #[tokio::test]
+    async fn should_not_fetch_missing_invitation() {
+        let db = MemoryRepo::seeded();
+
+        let by_id = fetch_invitation(PRIMARY, None, &db).await;
+
+        assert!(matches!(by_id, Err(ApiErr::InvalidToken)));
+        assert_eq!(db.is_opened(PRIMARY), Some(false));
+    }
+
+    #[tokio::test]
+    async fn should_preview_without_opening() {
+        let db = MemoryRepo::seeded();
+
+        let invitation = preview_invitation(PRIMARY, &db)
+            .await
+            .expect("Should preview invitation");
+
+        assert_eq!(invitation.dependents.len(), 2);
+        assert_eq!(db.is_opened(PRIMARY), Some(false));
+        assert_eq!(db.is_opened(DEPENDENT), Some(false));
+    }
+
+    #[tokio::test]
+    async fn should_update_household() {
+        let mut db = MemoryRepo::seeded();
+        let mut invitation = db.get_invitation(PRIMARY).await.unwrap();
+        invitation.primary_invitee = coming(invitation.primary_invitee);
+        invitation.dependents = invitation.dependents.into_iter().map(coming).collect();
+        let (token, _) = guest_invitation(&db).await;
+        let answers = GuestInvitation::new(&invitation, None);
+
+        let result = update_invitation(&token, &answers, None, &mut db)
+            .await
+            .expect("Should update invitation");
+
+        assert_eq!(result, answers);
+        assert_eq!(db.get_invitation(PRIMARY).await.unwrap(), invitation);
+    }
+
+    #[tokio::test]
+    async fn should_reject_answers_for_other_guests() {
+        let mut db = MemoryRepo::seeded();
+        let (token, mut answers) = guest_invitation(&db).await;
+        answers.primary_invitee.rsvp = Rsvp::Coming;
+        answers.dependents[0].fname = "Willian".to_string();
+
+        let result = update_invitation(&token, &answers, None, &mut db).await;
+
+        assert!(matches!(result, Err(ApiErr::NotInHousehold(name)) if name == "Willian Kwong"));
+        answers.dependents.pop();
+        answers.dependents[0].fname = "Joseph".to_string();
+        let result = update_invitation(&token, &answers, None, &mut db).await;
+        assert!(matches!(result, Err(ApiErr::ArgumentErr(_))));
+        let primary = db.get_invitee_by_id(PRIMARY).await.unwrap();
+        assert_eq!(primary.rsvp, Rsvp::Unknown);
+    }
+
+    #[tokio::test]
+    async fn should_reject_invitee_outside_household() {
+        let mut db = MemoryRepo::seeded();
+        let mut invitation = db.get_invitation(PRIMARY).await.unwrap();
+        invitation.primary_invitee = coming(invitation.primary_invitee);
+        let outsider = db.get_invitee_by_id(OUTSIDER).await.unwrap();
+        invitation.dependents.push(coming(outsider));
+
+        let result = override_invitation(&invitation, &mut db).await;
+
+        assert!(matches!(result, Err(ApiErr::NotInHousehold(id)) if id == OUTSIDER));
+        let primary = db.get_invitee_by_id(PRIMARY).await.unwrap();
+        let outsider = db.get_invitee_by_id(OUTSIDER).await.unwrap();
+        assert_eq!(primary.rsvp, Rsvp::Unknown);
+        assert_eq!(outsider.rsvp, Rsvp::Unknown);
+    }
+
+    #[tokio::test]
+    async fn should_rollback_primary_when_dependent_fails() {
+        let mut db = MemoryRepo::seeded();
+        let mut invitation = db.get_invitation(PRIMARY).await.unwrap();
+        db.insert_relation(PRIMARY, "missing");
+        invitation.primary_invitee = coming(invitation.primary_invitee);
+        let mut missing = invitation.primary_invitee.clone();
+        missing.id = "missing".to_string();
+        invitation.dependents.push(missing);
+
+        let result = override_invitation(&invitation, &mut db).await;
+
+        assert!(matches!(
+            result,
+            Err(ApiErr::RepoErr(RepoErr::ItemNotFound(_)))
+        ));
+        let primary = db.get_invitee_by_id(PRIMARY).await.unwrap();
+        assert_eq!(primary.rsvp, Rsvp::Unknown);
+        assert_eq!(primary.dietary_requirements, "");
+    }
+
+    #[tokio::test]
+    async fn should_update_dietary_profile() {
+        let mut db = MemoryRepo::seeded();
+        let (token, mut answers) = guest_invitation(&db).await;
+        let profile = DietaryProfile {
+            diet: Diet::Vegetarian,
+            allergies: vec![Allergy {
+                allergen: "Peanuts".to_string(),
+                severity: AllergySeverity::Severe,
+            }],
+            notes: "".to_string(),
+        };
+        answers.primary_invitee.dietary = Some(profile.clone());
+        answers.dependents[1].dietary = None;
+
+        update_invitation(&token, &answers, None, &mut db)
+            .await
+            .expect("Should update invitation");
+
+        let primary = db.get_invitee_by_id(PRIMARY).await.unwrap();
+        let dependent = db.get_invitee_by_id(DEPENDENT).await.unwrap();
+        assert_eq!(primary.dietary, Some(profile));
+        assert_eq!(dependent.dietary, Some(DietaryProfile::default()));
+    }
+
+    #[tokio::test]
+    async fn should_reject_invalid_dietary_profile() {
+        let mut db = MemoryRepo::seeded();
+        let (token, mut answers) = guest_invitation(&db).await;
+        answers.primary_invitee.rsvp = Rsvp::Coming;
+        let allergy = Allergy {
+            allergen: "peanuts".to_string(),
+            severity: AllergySeverity::Mild,
+        };
+        answers.dependents[0].dietary = Some(DietaryProfile {
+            allergies: vec![allergy.clone(), allergy],
+            ..Default::default()
+        });
+
+        let result = update_invitation(&token, &answers, None, &mut db).await;
+
+        assert!(matches!(result, Err(ApiErr::ArgumentErr(_))));
+        let primary = db.get_invitee_by_id(PRIMARY).await.unwrap();
+        assert_eq!(primary.rsvp, Rsvp::Unknown);
+    }
+
+    #[test]
+    fn other_diet_should_need_notes() {
+        let mut profile = DietaryProfile {
+            diet: Diet::Other,
+            ..Default::default()
+        };
+        assert!(validate_dietary(&profile).is_err());
+
+        profile.notes = "Low FODMAP".to_string();
+        assert!(validate_dietary(&profile).is_ok());
+    }
+}
@@ -0,0 +1,83 @@
+use super::*;
+
+pub const ADMIN_KEY_HEADER: &str = "x-admin-key";
+
+/// Handles an API Gateway proxy event, replying with a proxy response. Neither the body nor the
+/// result are logged, as they hold invitation tokens
+#[tracing::instrument(skip_all)]
+pub async fn handle_event(event: Value, app: Result<&App, &ConfigErr>) -> Result<Value, StdErr> {
+    let body = event.get("body");
+
+    event!(Level::INFO, "Lambda function called");
+
+    let body = match body {
+        Some(body) => body,
+        None => {
+            event!(Level::WARN, "Missing body");
+            return Ok(HttpError::from(ApiErr::ArgumentErr("Missing body".into())).into());
+        }
+    };
+
+    let body_str = match body.as_str() {
+        Some(body_str) => body_str,
+        None => {
+            event!(Level::WARN, "Non-string body");
+            return Ok(HttpError::from(ApiErr::ArgumentErr("Body is not a string".into())).into());
+        }
+    };
+
+    let params: Payload = match serde_json::from_str(body_str) {
+        Ok(params) => params,
+        Err(err) => {
+            let err: HttpError = HttpError::from(ApiErr::ArgumentErr(format!(
+                "Could not parse into a function with parameters: {}",
+                err
+            )));
+            event!(Level::WARN, "Invalid parameters");
+            return Ok(err.into());
+        }
+    };
+
+    let app = match app {
+        Ok(app) => app,
+        Err(err) => {
+            event!(
+                Level::ERROR,
+                "Cannot serve request with invalid configuration"
+            );
+            return Ok(HttpError::from(ApiErr::Unavailable(err.to_string())).into());
+        }
+    };
+
+    let role = app.config.role(header(&event, ADMIN_KEY_HEADER));
+
+    let client = match app.connection.checkout().await {
+        Ok(client) => client,
+        Err(err) => {
+            event!(Level::ERROR, "Failed to connect to db {}", err);
+            return Ok(HttpError::from(ApiErr::Unavailable(err.to_string())).into());
+        }
+    };
+
+    let mut db = DB { client };
+    let result = handle_request(
+        params,
+        role,
+        &mut db,
+        app.config.rsvp_deadline,
+        app.mail.as_ref(),
+    )
+    .await;
+    app.connection.checkin(db.client);
+
+    match result {
+        Ok(value) => {
+            event!(Level::INFO, "function result success");
+            Ok(lambda_response(json!({ "data": value }), 200))
+        }
+        Err(err) => {
+            event!(Level::ERROR, "function result err {}", err);
+            Ok(HttpError::from(err).into())
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use super::*;
+use tracing::{event, Level};
+
+/// The invitee's parent, when they are someone's dependent
+async fn find_parent<T: RelationRepo>(id: &str, db: &T) -> Result<Option<String>, ApiErr> {
+    let parent = db.get_parent(id).await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = parent {
+        event!(Level::ERROR, "Failed to find parent");
+        return Err(err);
+    }
+
+    parent
+}
+
+/// The ids of the household's dependents
+async fn find_dependents<T: RelationRepo>(id: &str, db: &T) -> Result<Vec<String>, ApiErr> {
+    let dependents = db.get_dependents(id).await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = dependents {
+        event!(Level::ERROR, "Failed to find dependents");
+        return Err(err);
+    }
+
+    dependents
+}
+
+async fn ensure_exists<T: InviteeRepo>(id: &str, db: &T) -> Result<(), ApiErr> {
+    if let Err(err) = db.get_invitee_by_id(id).await {
+        event!(Level::WARN, "Attempted to use missing invitee");
+        return Err(ApiErr::RepoErr(err));
+    }
+    Ok(())
+}
+
+async fn add_relation<T: RelationRepo>(parent: &str, child: &str, db: &T) -> Result<(), ApiErr> {
+    if let Err(err) = db.add_relation(parent, child).await {
+        event!(Level::ERROR, "Failed to add relation");
+        return Err(ApiErr::RepoErr(err));
+    }
+    Ok(())
+}
+
+async fn remove_relation<T: RelationRepo>(parent: &str, child: &str, db: &T) -> Result<(), ApiErr> {
+    if let Err(err) = db.remove_relation(parent, child).await {
+        event!(Level::ERROR, "Failed to remove relation");
+        return Err(ApiErr::RepoErr(err));
+    }
+    Ok(())
+}
+
+/// Checks the invitee exists and heads a household, rather than being someone's dependent
+pub(crate) async fn ensure_primary<T: InviteeRepo + RelationRepo>(
+    id: &str,
+    db: &T,
+) -> Result<(), ApiErr> {
+    ensure_exists(id, db).await?;
+
+    if let Some(parent) = find_parent(id, db).await? {
+        return Err(ApiErr::HouseholdConflict(format!(
+            "{} is a dependent of {}",
+            id, parent
+        )));
+    }
+    Ok(())
+}
+
+/// Checks the invitee has no dependents, so can be made someone else's dependent or deleted
+pub(crate) async fn ensure_no_dependents<T: InviteeRepo + RelationRepo>(
+    id: &str,
+    db: &T,
+) -> Result<(), ApiErr> {
+    ensure_exists(id, db).await?;
+
+    if !find_dependents(id, db).await?.is_empty() {
+        return Err(ApiErr::HouseholdConflict(format!(
+            "{} has dependents of their own",
+            id
+        )));
+    }
+    Ok(())
+}
+
+/// Removes what belonged to the invitee's own household once they are someone's dependent, so the
+/// link they were sent stops working and no deadline or reminder settings are left behind
+pub(crate) async fn dissolve_household<T: ReminderRepo + DeadlineRepo + TokenRepo>(
+    id: &str,
+    db: &T,
+) -> Result<(), ApiErr> {
+    if let Err(err) = db.revoke_tokens(id).await {
+        event!(Level::ERROR, "Failed to revoke tokens");
+        return Err(ApiErr::RepoErr(err));
+    }
+
+    if let Err(err) = db.set_rsvp_deadline(id, None).await {
+        event!(Level::ERROR, "Failed to clear rsvp deadline");
+        return Err(ApiErr::RepoErr(err));
+    }
+
+    let settings = ReminderSettings {
+        household: id.to_string(),
+        cadence_days: None,
+        max_reminders: None,
+    };
+    if let Err(err) = db.set_reminder_settings(&settings).await {
+        event!(Level::ERROR, "Failed to clear reminder settings");
+        return Err(ApiErr::RepoErr(err));
+    }
+    Ok(())
+}
+
+async fn households<T: InviteeRepo>(ids: &[&str], db: &T) -> Result<Vec<InvitationATO>, ApiErr> {
+    let mut households = vec![];
+    for id in ids {
+        let household = db.get_invitation(id).await.map_err(ApiErr::RepoErr);
+
+        if let Err(err) = household {
+            event!(Level::ERROR, "Failed to get household");
+            return Err(err);
+        }
+
+        households.push(household.expect("Should handle err"));
+    }
+    Ok(households)
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn attach_dependent<T: UnitOfWork>(
+    primary: &str,
+    dependent: &str,
+    db: &mut T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    let tx = begin_transaction(db).await?;
+    let result = attach(primary, dependent, &tx).await;
+    finish_transaction(tx, result).await
+}
+
+async fn attach<T: InviteeRepo + RelationRepo + ReminderRepo + DeadlineRepo + TokenRepo>(
+    primary: &str,
+    dependent: &str,
+    db: &T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    if primary == dependent {
+        return Err(ApiErr::HouseholdConflict(format!(
+            "{} cannot be their own dependent",
+            primary
+        )));
+    }
+    ensure_primary(primary, db).await?;
+    ensure_no_dependents(dependent, db).await?;
+
+    if let Some(parent) = find_parent(dependent, db).await? {
+        return Err(ApiErr::HouseholdConflict(format!(
+            "{} is already a dependent of {}",
+            dependent, parent
+        )));
+    }
+
+    add_relation(primary, dependent, db).await?;
+    dissolve_household(dependent, db).await?;
+    households(&[primary], db).await
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn detach_dependent<T: UnitOfWork>(
+    dependent: &str,
+    db: &mut T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    let tx = begin_transaction(db).await?;
+    let result = detach(dependent, &tx).await;
+    finish_transaction(tx, result).await
+}
+
+async fn detach<T: InviteeRepo + RelationRepo>(
+    dependent: &str,
+    db: &T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    let parent = find_parent(dependent, db).await?;
+    if parent.is_none() {
+        return Err(ApiErr::HouseholdConflict(format!(
+            "{} is not a dependent",
+            dependent
+        )));
+    }
+    let parent = parent.expect("Should handle None");
+
+    remove_relation(&parent, dependent, db).await?;
+    households(&[&parent, dependent], db).await
+}
+
+#[tracing::instrument(skip(db))]
+pub async fn move_dependent<T: UnitOfWork>(
+    dependent: &str,
+    primary: &str,
+    db: &mut T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    let tx = begin_transaction(db).await?;
+    let result = move_to(dependent, primary, &tx).await;
+    finish_transaction(tx, result).await
+}
+
+async fn move_to<T: InviteeRepo + RelationRepo + ReminderRepo + DeadlineRepo + TokenRepo>(
+    dependent: &str,
+    primary: &str,
+    db: &T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    let parent = find_parent(dependent, db).await?;
+
+    if let Some(parent) = &parent {
+        if parent == primary {
+            return households(&[primary], db).await;
+        }
+        remove_relation(parent, dependent, db).await?;
+    }
+    attach(primary, dependent, db).await?;
+
+    match &parent {
+        Some(parent) => households(&[parent, primary], db).await,
+        None => households(&[primary], db).await,
+    }
+}
+
+/// Moves the `from` household, including its primary invitee, into the `into` household
+#[tracing::instrument(skip(db))]
+pub async fn merge_households<T: UnitOfWork>(
+    from: &str,
+    into: &str,
+    db: &mut T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    let tx = begin_transaction(db).await?;
+    let result = merge(from, into, &tx).await;
+    finish_transaction(tx, result).await
+}
+
+async fn merge<T: InviteeRepo + RelationRepo + ReminderRepo + DeadlineRepo + TokenRepo>(
+    from: &str,
+    into: &str,
+    db: &T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    if from == into {
+        return Err(ApiErr::HouseholdConflict(format!(
+            "{} cannot be merged into itself",
+            from
+        )));
+    }
+    ensure_primary(from, db).await?;
+    ensure_primary(into, db).await?;
+
+    for dependent in find_dependents(from, db).await? {
+        remove_relation(from, &dependent, db).await?;
+        add_relation(into, &dependent, db).await?;
+    }
+    add_relation(into, from, db).await?;
+    dissolve_household(from, db).await?;
+
+    households(&[into], db).await
+}
+
+/// Makes one of the household's dependents the primary of a new household, taking the given
+/// dependents with them
+#[tracing::instrument(skip(db))]
+pub async fn split_household<T: UnitOfWork>(
+    primary: &str,
+    new_primary: &str,
+    dependents: &[String],
+    db: &mut T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    let tx = begin_transaction(db).await?;
+    let result = split(primary, new_primary, dependents, &tx).await;
+    finish_transaction(tx, result).await
+}
+
+async fn split<T: InviteeRepo + RelationRepo>(
+    primary: &str,
+    new_primary: &str,
+    dependents: &[String],
+    db: &T,
+) -> Result<Vec<InvitationATO>, ApiErr> {
+    ensure_primary(primary, db).await?;
+    let household = find_dependents(primary, db).await?;
+
+    let outsider = std::iter::once(new_primary)
+        .chain(dependents.iter().map(|e| e.as_str()))
+        .find(|e| !household.iter().any(|member| member == e));
+
+    if let Some(outsider) = outsider {
+        event!(
+            Level::WARN,
+            "Attempted to split off invitee outside of household"
+        );
+        return Err(ApiErr::HouseholdConflict(format!(
+            "{} is not a dependent of {}",
+            outsider, primary
+        )));
+    }
+
+    remove_relation(primary, new_primary, db).await?;
+    for dependent in dependents.iter().filter(|e| *e != new_primary) {
+        remove_relation(primary, dependent, db).await?;
+        add_relation(new_primary, dependent, db).await?;
+    }
+
+    households(&[primary, new_primary], db).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+    const DEPENDENT2: &str = "0c6c0fc4-146f-4e2a-b081-7f3ec5281290";
+    const SINGLE: &str = "e01601bb-0647-410f-91b5-ca4df097c175";
+
+    fn ids(invitation: &InvitationATO) -> Vec<&str> {
+        invitation
+            .dependents
+            .iter()
+            .map(|e| e.id.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn should_attach_dependent() {
+        let mut db = MemoryRepo::seeded();
+
+        let households = attach_dependent(PRIMARY, SINGLE, &mut db)
+            .await
+            .expect("Should attach dependent");
+
+        assert_eq!(ids(&households[0]), vec![DEPENDENT, DEPENDENT2, SINGLE]);
+    }
+
+    #[tokio::test]
+    async fn should_not_attach_to_dependent() {
+        let mut db = MemoryRepo::seeded();
+
+        let result = attach_dependent(DEPENDENT, SINGLE, &mut db).await;
+
+        assert!(matches!(result, Err(ApiErr::HouseholdConflict(_))));
+    }
+
+    #[tokio::test]
+    async fn should_not_attach_primary_or_second_parent() {
+        let mut db = MemoryRepo::seeded();
+
+        let primary = attach_dependent(SINGLE, PRIMARY, &mut db).await;
+        let second_parent = attach_dependent(SINGLE, DEPENDENT, &mut db).await;
+
+        assert!(matches!(primary, Err(ApiErr::HouseholdConflict(_))));
+        assert!(matches!(second_parent, Err(ApiErr::HouseholdConflict(_))));
+        assert_eq!(db.get_parent(DEPENDENT).await.unwrap().unwrap(), PRIMARY);
+    }
+
+    #[tokio::test]
+    async fn should_detach_dependent() {
+        let mut db = MemoryRepo::seeded();
+
+        let households = detach_dependent(DEPENDENT, &mut db)
+            .await
+            .expect("Should detach dependent");
+
+        assert_eq!(ids(&households[0]), vec![DEPENDENT2]);
+        assert_eq!(households[1].primary_invitee.id, DEPENDENT);
+        assert!(detach_dependent(DEPENDENT, &mut db).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_move_dependent() {
+        let mut db = MemoryRepo::seeded();
+
+        let households = move_dependent(DEPENDENT, SINGLE, &mut db)
+            .await
+            .expect("Should move dependent");
+
+        assert_eq!(ids(&households[0]), vec![DEPENDENT2]);
+        assert_eq!(ids(&households[1]), vec![DEPENDENT]);
+    }
+
+    #[tokio::test]
+    async fn should_merge_households() {
+        let mut db = MemoryRepo::seeded();
+
+        let households = merge_households(PRIMARY, SINGLE, &mut db)
+            .await
+            .expect("Should merge households");
+
+        assert_eq!(households[0].primary_invitee.id, SINGLE);
+        assert_eq!(ids(&households[0]), vec![DEPENDENT, DEPENDENT2, PRIMARY]);
+    }
+
+    #[tokio::test]
+    async fn merged_household_should_lose_token_deadline_and_settings() {
+        let mut db = MemoryRepo::seeded();
+        let token = household_token(PRIMARY, &db).await.unwrap();
+        set_rsvp_deadline(
+            &RsvpDeadline {
+                household: PRIMARY.to_string(),
+                deadline: Some(chrono::Utc::now()),
+            },
+            &db,
+        )
+        .await
+        .unwrap();
+        set_reminder_settings(
+            &ReminderSettings {
+                household: PRIMARY.to_string(),
+                cadence_days: Some(1),
+                max_reminders: None,
+            },
+            &db,
+        )
+        .await
+        .unwrap();
+
+        merge_households(PRIMARY, SINGLE, &mut db).await.unwrap();
+
+        assert!(matches!(
+            fetch_invitation(&token, None, &db).await,
+            Err(ApiErr::InvalidToken)
+        ));
+        assert_eq!(db.get_rsvp_deadline(PRIMARY).await.unwrap(), None);
+        assert!(!db.has_reminder_settings(PRIMARY));
+    }
+
+    #[tokio::test]
+    async fn attached_invitee_should_lose_token() {
+        let mut db = MemoryRepo::seeded();
+        let token = household_token(SINGLE, &db).await.unwrap();
+
+        attach_dependent(PRIMARY, SINGLE, &mut db).await.unwrap();
+
+        assert!(matches!(
+            token_household(&token, &db).await,
+            Err(ApiErr::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_split_household() {
+        let mut db = MemoryRepo::seeded();
+
+        let households = split_household(PRIMARY, DEPENDENT, &[DEPENDENT2.to_string()], &mut db)
+            .await
+            .expect("Should split household");
+
+        assert!(ids(&households[0]).is_empty());
+        assert_eq!(households[1].primary_invitee.id, DEPENDENT);
+        assert_eq!(ids(&households[1]), vec![DEPENDENT2]);
+    }
+
+    #[tokio::test]
+    async fn should_not_split_off_outsider() {
+        let mut db = MemoryRepo::seeded();
+
+        let result = split_household(PRIMARY, DEPENDENT, &[SINGLE.to_string()], &mut db).await;
+
+        assert!(matches!(result, Err(ApiErr::HouseholdConflict(_))));
+        assert_eq!(db.get_dependents(PRIMARY).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,507 @@
+use super::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use tracing::{event, Level};
+use uuid::Uuid;
+
+/// A row of the guest list spreadsheet, the first guest of each household is its primary invitee
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestRecord {
+    pub fname: String,
+    pub lname: String,
+    pub household: String,
+    pub email: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CsvRow {
+    fname: String,
+    lname: String,
+    household: String,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    tags: Option<String>,
+}
+
+/// Reads a guest list with the headers `fname,lname,household,email,tags`, tags being separated
+/// by `;`
+pub fn parse_guest_csv<R: Read>(reader: R) -> Result<Vec<GuestRecord>, ApiErr> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let mut records = vec![];
+    for row in reader.deserialize::<CsvRow>() {
+        let row = row.map_err(|e| ApiErr::ArgumentErr(format!("Invalid guest list: {}", e)))?;
+        validate_name(&row.fname, &row.lname)?;
+        if row.household.is_empty() {
+            return Err(ApiErr::ArgumentErr(format!(
+                "{} {} must belong to a household",
+                row.fname, row.lname
+            )));
+        }
+
+        let mut tags: Vec<String> = row
+            .tags
+            .unwrap_or_default()
+            .split(';')
+            .map(|e| e.trim().to_string())
+            .filter(|e| !e.is_empty())
+            .collect();
+        tags.sort();
+        tags.dedup();
+
+        records.push(GuestRecord {
+            fname: row.fname,
+            lname: row.lname,
+            household: row.household,
+            email: row.email.filter(|e| !e.is_empty()),
+            tags,
+        });
+    }
+    Ok(records)
+}
+
+/// A single difference between the guest list and the database
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq))]
+pub enum GuestChange {
+    Create {
+        entry: GuestListEntry,
+        household: String,
+    },
+    Update {
+        before: Box<GuestListEntry>,
+        after: Box<GuestListEntry>,
+        household: String,
+    },
+    Delete {
+        entry: GuestListEntry,
+    },
+}
+
+fn describe_email(email: &Option<String>) -> &str {
+    email.as_deref().unwrap_or("-")
+}
+
+fn describe_tags(tags: &[String]) -> String {
+    if tags.is_empty() {
+        return "-".to_string();
+    }
+    tags.join(";")
+}
+
+impl fmt::Display for GuestChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuestChange::Create { entry, household } => write!(
+                f,
+                "+ {} {} (household: {}, email: {}, tags: {})",
+                entry.invitee.fname,
+                entry.invitee.lname,
+                household,
+                describe_email(&entry.email),
+                describe_tags(&entry.tags)
+            ),
+            GuestChange::Update {
+                before,
+                after,
+                household,
+            } => {
+                let mut changes = vec![];
+                if before.invitee.fname != after.invitee.fname
+                    || before.invitee.lname != after.invitee.lname
+                {
+                    changes.push(format!(
+                        "name {} {} -> {} {}",
+                        before.invitee.fname,
+                        before.invitee.lname,
+                        after.invitee.fname,
+                        after.invitee.lname
+                    ));
+                }
+                if before.parent != after.parent {
+                    changes.push(format!("household -> {}", household));
+                }
+                if before.email != after.email {
+                    changes.push(format!(
+                        "email {} -> {}",
+                        describe_email(&before.email),
+                        describe_email(&after.email)
+                    ));
+                }
+                if before.tags != after.tags {
+                    changes.push(format!(
+                        "tags {} -> {}",
+                        describe_tags(&before.tags),
+                        describe_tags(&after.tags)
+                    ));
+                }
+                write!(
+                    f,
+                    "~ {} {}: {}",
+                    after.invitee.fname,
+                    after.invitee.lname,
+                    changes.join(", ")
+                )
+            }
+            GuestChange::Delete { entry } => {
+                write!(f, "- {} {}", entry.invitee.fname, entry.invitee.lname)
+            }
+        }
+    }
+}
+
+fn name_key(fname: &str, lname: &str) -> String {
+    format!("{} {}", fname.trim(), lname.trim()).to_lowercase()
+}
+
+/// Works out the changes which turn the current guest list into the imported one. Guests are
+/// matched by name, ignoring case, so their rsvp and dietary requirements are kept
+pub fn diff_guest_list(
+    current: &[GuestListEntry],
+    records: &[GuestRecord],
+) -> Result<Vec<GuestChange>, ApiErr> {
+    let mut existing: HashMap<String, &GuestListEntry> = HashMap::new();
+    for entry in current {
+        let key = name_key(&entry.invitee.fname, &entry.invitee.lname);
+        if existing.insert(key, entry).is_some() {
+            return Err(ApiErr::ArgumentErr(format!(
+                "Several invitees are named {} {}, rename them before importing",
+                entry.invitee.fname, entry.invitee.lname
+            )));
+        }
+    }
+
+    let mut matched: Vec<(&GuestRecord, Option<&GuestListEntry>, String)> = vec![];
+    for record in records {
+        let key = name_key(&record.fname, &record.lname);
+        if matched
+            .iter()
+            .any(|(e, _, _)| name_key(&e.fname, &e.lname) == key)
+        {
+            return Err(ApiErr::ArgumentErr(format!(
+                "{} {} is listed more than once",
+                record.fname, record.lname
+            )));
+        }
+
+        let entry = existing.remove(&key);
+        let id = entry
+            .map(|e| e.invitee.id.clone())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        matched.push((record, entry, id));
+    }
+
+    let mut primaries: HashMap<&str, &str> = HashMap::new();
+    for (record, _, id) in &matched {
+        primaries.entry(record.household.as_str()).or_insert(id);
+    }
+
+    let mut changes = vec![];
+    for (record, entry, id) in &matched {
+        let primary = primaries[record.household.as_str()];
+        let invitee = match entry {
+            Some(entry) => InviteeDTO {
+                fname: record.fname.clone(),
+                lname: record.lname.clone(),
+                ..entry.invitee.clone()
+            },
+            None => InviteeDTO {
+                id: id.clone(),
+                fname: record.fname.clone(),
+                lname: record.lname.clone(),
+                rsvp: Rsvp::Unknown,
+                dietary_requirements: "".to_string(),
+                dietary: None,
+            },
+        };
+        let after = GuestListEntry {
+            invitee,
+            parent: (primary != id).then(|| primary.to_string()),
+            email: record.email.clone(),
+            tags: record.tags.clone(),
+            invitation_opened: entry.map(|e| e.invitation_opened).unwrap_or(false),
+        };
+
+        match entry {
+            None => changes.push(GuestChange::Create {
+                entry: after,
+                household: record.household.clone(),
+            }),
+            Some(before)
+                if before.invitee.fname != after.invitee.fname
+                    || before.invitee.lname != after.invitee.lname
+                    || before.parent != after.parent
+                    || before.email != after.email
+                    || before.tags != after.tags =>
+            {
+                changes.push(GuestChange::Update {
+                    before: Box::new((*before).clone()),
+                    after: Box::new(after),
+                    household: record.household.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    // Keeps the deletes in the same order as the current guest list
+    for entry in current {
+        let key = name_key(&entry.invitee.fname, &entry.invitee.lname);
+        if existing.contains_key(&key) {
+            changes.push(GuestChange::Delete {
+                entry: entry.clone(),
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Diffs the guest list against the database without writing anything
+#[tracing::instrument(skip_all)]
+pub async fn preview_guest_import<T: GuestListRepo>(
+    records: &[GuestRecord],
+    db: &T,
+) -> Result<Vec<GuestChange>, ApiErr> {
+    let current = db.get_guest_list().await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = current {
+        event!(Level::ERROR, "Failed to get guest list");
+        return Err(err);
+    }
+
+    diff_guest_list(&current.expect("Should handle err"), records)
+}
+
+/// Diffs the guest list against the database and applies the changes in a single transaction
+#[tracing::instrument(skip_all)]
+pub async fn import_guest_list<T: UnitOfWork>(
+    records: &[GuestRecord],
+    db: &mut T,
+) -> Result<Vec<GuestChange>, ApiErr> {
+    let tx = begin_transaction(db).await?;
+    let result = write_guest_list(records, &tx).await;
+    finish_transaction(tx, result).await
+}
+
+async fn write_guest_list<
+    T: InviteeRepo + RelationRepo + GuestListRepo + ReminderRepo + DeadlineRepo + TokenRepo,
+>(
+    records: &[GuestRecord],
+    db: &T,
+) -> Result<Vec<GuestChange>, ApiErr> {
+    let changes = preview_guest_import(records, db).await?;
+
+    // Old relations go first, so a guest is never part of two households at once
+    for change in &changes {
+        if let GuestChange::Update { before, after, .. } = change {
+            if let (Some(parent), true) = (&before.parent, before.parent != after.parent) {
+                db.remove_relation(parent, &before.invitee.id).await?;
+            }
+        }
+    }
+
+    for change in &changes {
+        if let GuestChange::Delete { entry } = change {
+            db.delete_invitee(&entry.invitee.id).await?;
+        }
+    }
+
+    for change in &changes {
+        match change {
+            GuestChange::Create { entry, .. } => {
+                db.create_invitee(&entry.invitee).await?;
+            }
+            GuestChange::Update { before, after, .. }
+                if before.invitee.fname != after.invitee.fname
+                    || before.invitee.lname != after.invitee.lname =>
+            {
+                // Re-read so an rsvp made since the diff is not overwritten
+                let mut invitee = db.get_invitee_by_id(&after.invitee.id).await?;
+                invitee.fname = after.invitee.fname.clone();
+                invitee.lname = after.invitee.lname.clone();
+                db.edit_invitee(&invitee).await?;
+            }
+            _ => {}
+        }
+    }
+
+    for change in &changes {
+        let (before, after) = match change {
+            GuestChange::Create { entry, .. } => (None, entry),
+            GuestChange::Update { before, after, .. } => (Some(&**before), &**after),
+            GuestChange::Delete { .. } => continue,
+        };
+        let id = &after.invitee.id;
+
+        if before.map(|e| &e.parent) != Some(&after.parent) {
+            if let Some(parent) = &after.parent {
+                db.add_relation(parent, id).await?;
+            }
+        }
+        // A primary moved into another household loses their link, deadline and reminders
+        if before.is_some_and(|e| e.parent.is_none()) && after.parent.is_some() {
+            dissolve_household(id, db).await?;
+        }
+        if before.map(|e| &e.email) != Some(&after.email) {
+            db.set_email(id, after.email.as_deref()).await?;
+        }
+        if before.map(|e| &e.tags) != Some(&after.tags) {
+            db.set_tags(id, &after.tags).await?;
+        }
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+
+    const GUEST_LIST: &str = "fname,lname,household,email,tags
+David,Kwong,kwong,david@example.com,family
+Mia,Huang,kwong,,family; bridal party
+Willian,Kwong,willian,,
+Anna,Lee,lee,anna@example.com,uni
+";
+
+    #[test]
+    fn should_parse_guest_csv() {
+        let records = parse_guest_csv(GUEST_LIST.as_bytes()).expect("Should parse guest list");
+
+        assert_eq!(records.len(), 4);
+        assert_eq!(
+            records[1],
+            GuestRecord {
+                fname: "Mia".to_string(),
+                lname: "Huang".to_string(),
+                household: "kwong".to_string(),
+                email: None,
+                tags: vec!["bridal party".to_string(), "family".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn should_refuse_guest_without_household() {
+        let result = parse_guest_csv("fname,lname,household\nAnna,Lee,\n".as_bytes());
+
+        assert!(matches!(result, Err(ApiErr::ArgumentErr(_))));
+    }
+
+    #[tokio::test]
+    async fn should_preview_without_writing() {
+        let db = MemoryRepo::seeded();
+        let records = parse_guest_csv(GUEST_LIST.as_bytes()).unwrap();
+
+        let changes = preview_guest_import(&records, &db)
+            .await
+            .expect("Should diff guest list");
+
+        let summary: Vec<String> = changes.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            summary,
+            vec![
+                "~ David Kwong: email - -> david@example.com, tags - -> family",
+                "~ Mia Huang: tags - -> bridal party;family",
+                "+ Anna Lee (household: lee, email: anna@example.com, tags: uni)",
+                "- Joseph Kwong",
+            ]
+        );
+        assert_eq!(db.get_guest_list().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn should_import_guest_list() {
+        let mut db = MemoryRepo::seeded();
+        let records = parse_guest_csv(GUEST_LIST.as_bytes()).unwrap();
+
+        import_guest_list(&records, &mut db)
+            .await
+            .expect("Should import guest list");
+
+        let guests = db.get_guest_list().await.unwrap();
+        let names: Vec<&str> = guests.iter().map(|e| e.invitee.fname.as_str()).collect();
+        assert_eq!(names, vec!["Mia", "David", "Willian", "Anna"]);
+        assert_eq!(guests[0].parent.as_deref(), Some(PRIMARY));
+        assert_eq!(guests[1].email.as_deref(), Some("david@example.com"));
+        assert_eq!(db.get_dependents(PRIMARY).await.unwrap(), vec![DEPENDENT]);
+
+        let changes = preview_guest_import(&records, &db).await.unwrap();
+        assert!(changes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_move_guests_between_households() {
+        let mut db = MemoryRepo::seeded();
+        let records = parse_guest_csv(
+            "fname,lname,household
+Mia,Huang,huang
+David,Kwong,huang
+Joseph,Kwong,joseph
+Willian,Kwong,joseph
+"
+            .as_bytes(),
+        )
+        .unwrap();
+
+        import_guest_list(&records, &mut db)
+            .await
+            .expect("Should import guest list");
+
+        assert_eq!(db.get_dependents(DEPENDENT).await.unwrap(), vec![PRIMARY]);
+        assert_eq!(db.get_parent(DEPENDENT).await.unwrap(), None);
+        assert_eq!(
+            db.get_dependents("0c6c0fc4-146f-4e2a-b081-7f3ec5281290")
+                .await
+                .unwrap(),
+            vec!["e01601bb-0647-410f-91b5-ca4df097c175"]
+        );
+    }
+
+    #[tokio::test]
+    async fn moved_primary_should_lose_token_and_deadline() {
+        let mut db = MemoryRepo::seeded();
+        let token = household_token(PRIMARY, &db).await.unwrap();
+        db.set_rsvp_deadline(PRIMARY, Some(chrono::Utc::now()))
+            .await
+            .unwrap();
+        let moved =
+            parse_guest_csv("fname,lname,household\nMia,Huang,a\nDavid,Kwong,a\n".as_bytes())
+                .unwrap();
+        let restored =
+            parse_guest_csv("fname,lname,household\nDavid,Kwong,a\nMia,Huang,a\n".as_bytes())
+                .unwrap();
+
+        import_guest_list(&moved, &mut db).await.unwrap();
+        import_guest_list(&restored, &mut db).await.unwrap();
+
+        assert_eq!(db.get_parent(PRIMARY).await.unwrap(), None);
+        assert!(matches!(
+            token_household(&token, &db).await,
+            Err(ApiErr::InvalidToken)
+        ));
+        assert_eq!(db.get_rsvp_deadline(PRIMARY).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn should_refuse_duplicate_guests() {
+        let db = MemoryRepo::seeded();
+        let records =
+            parse_guest_csv("fname,lname,household\nMia,Huang,a\nmia,huang,b\n".as_bytes())
+                .unwrap();
+
+        let result = preview_guest_import(&records, &db).await;
+
+        assert!(matches!(result, Err(ApiErr::ArgumentErr(_))));
+    }
+}
@@ -0,0 +1,196 @@
+use super::*;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{event, Level};
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SendFailure {
+    pub invitee: String,
+    pub email: String,
+    pub reason: String,
+}
+
+/// Which invitees were emailed, those which failed are left to be sent again
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SendReport {
+    pub sent: Vec<String>,
+    pub failed: Vec<SendFailure>,
+}
+
+/// Emails each household with an invitee who has not been sent one a link to their invitation,
+/// at the primary invitee's address when they have one. Every member's invite is marked as sent
+/// once it was delivered, so the household is emailed once
+#[tracing::instrument(skip_all)]
+pub async fn send_invitations<
+    T: InviteeRepo + RelationRepo + EmailRepo + DeadlineRepo + TokenRepo,
+    M: Mailer,
+>(
+    global: Option<DateTime<Utc>>,
+    db: &T,
+    mail: &Mail<M>,
+) -> Result<SendReport, ApiErr> {
+    let emails = db.get_unsent_emails().await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = emails {
+        event!(Level::ERROR, "Failed to get unsent emails");
+        return Err(err);
+    }
+    let emails = emails.expect("Should handle err");
+
+    let mut report = SendReport::default();
+    for household in emails.chunk_by(|a, b| a.household == b.household) {
+        // The repo lists the primary invitee's address first
+        let email = &household[0];
+        let failed = |reason: String| {
+            household.iter().map(move |e| SendFailure {
+                invitee: e.invitee.clone(),
+                email: e.email.clone(),
+                reason: reason.clone(),
+            })
+        };
+
+        let invitation = db.get_invitation(&email.household).await;
+        if let Err(err) = invitation {
+            event!(Level::ERROR, msg = "Failed to get invitation", ?email);
+            report.failed.extend(failed(err.to_string()));
+            continue;
+        }
+        let invitation = invitation.expect("Should handle err");
+
+        let token = household_token(&email.household, db).await;
+        if let Err(err) = token {
+            report.failed.extend(failed(err.to_string()));
+            continue;
+        }
+
+        let deadline = deadline_text(&email.household, global, db).await;
+        if let Err(err) = deadline {
+            report.failed.extend(failed(err.to_string()));
+            continue;
+        }
+
+        let message = render_email(
+            Template::Invitation,
+            &TemplateContext {
+                invitation: &invitation,
+                link: mail.invitation_link(&token.expect("Should handle err")),
+                deadline: deadline.expect("Should handle err"),
+            },
+        )
+        .to(&email.email);
+        if let Err(err) = mail.send(&message).await {
+            event!(Level::WARN, msg = "Failed to send invitation", ?email);
+            report.failed.extend(failed(err.to_string()));
+            continue;
+        }
+
+        for member in household {
+            // The email is out, so a failure here is reported but the invite may be sent twice
+            if let Err(err) = db.mark_invite_sent(&member.invitee).await {
+                event!(Level::ERROR, msg = "Failed to mark invite sent", ?member);
+                report.failed.push(SendFailure {
+                    invitee: member.invitee.clone(),
+                    email: member.email.clone(),
+                    reason: format!("Sent, but not recorded: {}", err),
+                });
+                continue;
+            }
+
+            report.sent.push(member.invitee.clone());
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+    const DEPENDENT2: &str = "0c6c0fc4-146f-4e2a-b081-7f3ec5281290";
+    const SINGLE: &str = "e01601bb-0647-410f-91b5-ca4df097c175";
+
+    fn mail() -> Mail<MemoryMailer> {
+        Mail {
+            mailer: MemoryMailer::new(),
+            from: "couple@example.com".to_string(),
+            invitation_url: "https://example.com/?token={token}".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_send_household_link_and_mark_sent() {
+        let db = MemoryRepo::seeded();
+        db.set_email(DEPENDENT, Some("mia@example.com"))
+            .await
+            .unwrap();
+        let mail = mail();
+
+        let report = send_invitations(None, &db, &mail)
+            .await
+            .expect("Should send invitations");
+
+        assert_eq!(report.sent, vec![DEPENDENT]);
+        let sent = mail.mailer.sent();
+        assert_eq!(sent[0].to, "mia@example.com");
+        let token = household_token(PRIMARY, &db).await.unwrap();
+        assert!(sent[0]
+            .text
+            .contains(&format!("https://example.com/?token={}", token)));
+        assert!(!sent[0].text.contains(PRIMARY));
+        assert!(sent[0].text.contains("David, Mia and Joseph"));
+        assert!(db.is_invite_sent(DEPENDENT));
+
+        let report = send_invitations(None, &db, &mail).await.unwrap();
+        assert!(report.sent.is_empty());
+        assert_eq!(mail.mailer.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_email_household_once_at_primary_address() {
+        let db = MemoryRepo::seeded();
+        db.set_email(PRIMARY, Some("david@example.com"))
+            .await
+            .unwrap();
+        db.set_email(DEPENDENT, Some("mia@example.com"))
+            .await
+            .unwrap();
+        db.set_email(DEPENDENT2, Some("joseph@example.com"))
+            .await
+            .unwrap();
+        let mail = mail();
+
+        let report = send_invitations(None, &db, &mail).await.unwrap();
+
+        let sent = mail.mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "david@example.com");
+        assert_eq!(report.sent, vec![PRIMARY, DEPENDENT2, DEPENDENT]);
+        assert!(db.is_invite_sent(DEPENDENT));
+        assert!(db.is_invite_sent(DEPENDENT2));
+    }
+
+    #[tokio::test]
+    async fn should_leave_failed_invite_unsent() {
+        let db = MemoryRepo::seeded();
+        db.set_email(PRIMARY, Some("david@example.com"))
+            .await
+            .unwrap();
+        db.set_email(SINGLE, Some("willian@example.com"))
+            .await
+            .unwrap();
+        let mail = mail();
+        mail.mailer.fail_for("willian@example.com");
+
+        let report = send_invitations(None, &db, &mail).await.unwrap();
+
+        assert_eq!(report.sent, vec![PRIMARY]);
+        assert_eq!(report.failed[0].invitee, SINGLE);
+        assert!(db.is_invite_sent(PRIMARY));
+        assert!(!db.is_invite_sent(SINGLE));
+    }
+}
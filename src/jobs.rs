@@ -0,0 +1,40 @@
+use super::*;
+use std::time::SystemTime;
+
+/// Runs a background job, replying with what it did
+#[tracing::instrument(skip(app))]
+pub async fn run_job(job: Job, app: &App) -> Result<Value, StdErr> {
+    let client = app.connection.checkout().await?;
+    let mut db = DB { client };
+
+    let result = match job {
+        Job::DeliverOutbox => match app.mail.as_ref() {
+            Some(mail) => deliver_outbox(app.config.rsvp_deadline, &db, mail)
+                .await
+                .map(|e| json!(e)),
+            None => Err(ApiErr::Unavailable("Email is not configured".into())),
+        },
+        Job::SendReminders => send_reminders(&app.config.reminders, SystemTime::now(), &mut db)
+            .await
+            .map(|e| json!(e)),
+        Job::ImportGuests { csv, apply } => match parse_guest_csv(csv.as_bytes()) {
+            Ok(records) => {
+                let changes = if apply {
+                    import_guest_list(&records, &mut db).await
+                } else {
+                    preview_guest_import(&records, &db).await
+                };
+                changes.map(|changes| {
+                    let changes: Vec<String> = changes.iter().map(|e| e.to_string()).collect();
+                    json!({ "changes": changes, "applied": apply })
+                })
+            }
+            Err(err) => Err(ApiErr::ArgumentErr(err.to_string())),
+        },
+    };
+    app.connection.checkin(db.client);
+
+    let result = result?;
+    event!(Level::INFO, "Job finished {}", result);
+    Ok(result)
+}
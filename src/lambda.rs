@@ -6,26 +6,37 @@ pub struct HttpError {
     pub msg: Option<String>,
 }
 
-impl Into<Value> for HttpError {
-    fn into(self) -> Value {
-        let msg = match self.msg {
+impl From<HttpError> for Value {
+    fn from(err: HttpError) -> Self {
+        let msg = match err.msg {
             Some(description) => json!(description),
             None => Value::Null,
         };
         lambda_response(
-            json!({"err":{"msg": msg, "errType":self.err_type}}),
-            self.status_code,
+            json!({"err":{"msg": msg, "errType":err.err_type}}),
+            err.status_code,
         )
     }
 }
 
 pub fn lambda_response(body: Value, code: i32) -> Value {
-    return json!({
+    json!({
         "statusCode":code,
         "headers":{
             "Content-Type":"application/json",
             "Access-Control-Allow-Origin":"*"
         },
         "body":body.to_string()
-    });
+    })
+}
+
+/// Looks up a request header, API Gateway may or may not lowercase the header names
+pub fn header<'a>(event: &'a Value, name: &str) -> Option<&'a str> {
+    event
+        .get("headers")?
+        .as_object()?
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))?
+        .1
+        .as_str()
 }
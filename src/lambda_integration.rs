@@ -26,6 +26,36 @@ impl From<ApiErr> for HttpError {
                 err_type: "argument-err".to_string(),
                 msg: Some(err.to_string()),
             },
+            ApiErr::NotInHousehold(_) => Self {
+                status_code: 403,
+                err_type: "not-in-household".to_string(),
+                msg: Some(err.to_string()),
+            },
+            ApiErr::Unavailable(_) => Self {
+                status_code: 503,
+                err_type: "service-unavailable".to_string(),
+                msg: Some(err.to_string()),
+            },
+            ApiErr::Unauthorized => Self {
+                status_code: 401,
+                err_type: "unauthorized".to_string(),
+                msg: Some(err.to_string()),
+            },
+            ApiErr::HouseholdConflict(_) => Self {
+                status_code: 409,
+                err_type: "household-conflict".to_string(),
+                msg: Some(err.to_string()),
+            },
+            ApiErr::RsvpClosed(_) => Self {
+                status_code: 403,
+                err_type: "rsvp-closed".to_string(),
+                msg: Some(err.to_string()),
+            },
+            ApiErr::InvalidToken => Self {
+                status_code: 404,
+                err_type: "invalid-token".to_string(),
+                msg: Some(err.to_string()),
+            },
         }
     }
 }
@@ -1,12 +1,42 @@
+mod admin;
 mod api;
+mod caterer;
 mod db;
+mod deadline;
+mod events;
+mod export;
 mod func;
+mod household;
+mod import;
+mod invitations;
+mod mail;
+mod memory;
+mod migrations;
 mod models;
+mod outbox;
+mod reminders;
+mod templates;
+mod tokens;
 
+pub use admin::*;
 pub use api::*;
+pub use caterer::*;
 pub use db::*;
+pub use deadline::*;
+pub use events::*;
+pub use export::*;
 pub use func::*;
+pub use household::*;
+pub use import::*;
+pub use invitations::*;
+pub use mail::*;
+pub use memory::*;
+pub use migrations::*;
 pub use models::*;
+pub use outbox::*;
+pub use reminders::*;
+pub use templates::*;
+pub use tokens::*;
 
 #[cfg(test)]
 mod tests {
@@ -22,7 +52,7 @@ mod tests {
 
         let uri = env::var("WED_POSTGRES_URI").expect("Uri should be defined for test");
         let (_client, connection) =
-            tokio_postgres::connect(&format!("{}", uri), connector)
+            tokio_postgres::connect(&uri, connector)
                 .await
                 .expect("Connection should not fail");
 
@@ -0,0 +1,107 @@
+use std::convert::Infallible;
+use std::env;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::Map;
+
+use super::*;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:3000";
+
+/// Serves `POST /api` over plain http, wrapping each request up as an API Gateway proxy event
+pub async fn serve(app: Result<App, ConfigErr>) -> Result<(), StdErr> {
+    let addr: SocketAddr = env::var("LOCAL_ADDR")
+        .unwrap_or_else(|_| DEFAULT_ADDR.to_string())
+        .parse()?;
+    let app = Arc::new(app);
+
+    let make_service = make_service_fn(move |_| {
+        let app = app.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let app = app.clone();
+                async move { Ok::<_, Infallible>(respond(req, app.as_ref().as_ref()).await) }
+            }))
+        }
+    });
+
+    event!(Level::INFO, "Listening on http://{}/api", addr);
+    Server::bind(&addr).serve(make_service).await?;
+    Ok(())
+}
+
+async fn respond(req: Request<Body>, app: Result<&App, &ConfigErr>) -> Response<Body> {
+    if req.method() == Method::OPTIONS {
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Methods", "POST, OPTIONS")
+            .header(
+                "Access-Control-Allow-Headers",
+                format!("Content-Type, {}", ADMIN_KEY_HEADER),
+            )
+            .body(Body::empty())
+            .unwrap_or_default();
+    }
+
+    if req.method() != Method::POST || req.uri().path() != "/api" {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap_or_default();
+    }
+
+    let headers: Map<String, Value> = req
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), json!(value.to_str().ok()?))))
+        .collect();
+
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => String::from_utf8_lossy(&body).into_owned(),
+        Err(err) => {
+            let err = HttpError::from(ApiErr::ArgumentErr(format!("Unreadable body: {}", err)));
+            return into_response(err.into());
+        }
+    };
+
+    let event = json!({ "body": body, "headers": headers });
+    match handle_event(event, app).await {
+        Ok(value) => into_response(value),
+        Err(err) => {
+            event!(Level::ERROR, "Failed to handle request {}", err);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Converts a proxy response built by `lambda_response` back into an http response
+fn into_response(value: Value) -> Response<Body> {
+    let status = value
+        .get("statusCode")
+        .and_then(|e| e.as_u64())
+        .and_then(|e| StatusCode::from_u16(e as u16).ok())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    let mut builder = Response::builder().status(status);
+    if let Some(headers) = value.get("headers").and_then(|e| e.as_object()) {
+        for (name, value) in headers {
+            if let Some(value) = value.as_str() {
+                builder = builder.header(name, value);
+            }
+        }
+    }
+
+    let body = value
+        .get("body")
+        .and_then(|e| e.as_str())
+        .unwrap_or_default()
+        .to_string();
+    builder.body(Body::from(body)).unwrap_or_default()
+}
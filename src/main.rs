@@ -1,19 +1,60 @@
+mod cli;
+mod config;
+mod connection;
+#[cfg(not(feature = "local"))]
+mod direct;
+mod gateway;
+#[cfg(not(feature = "local"))]
+mod jobs;
 mod lambda;
 mod lambda_integration;
-
-use std::env;
-
+#[cfg(feature = "local")]
+mod local;
+#[cfg(not(feature = "local"))]
+mod scheduled;
+#[cfg(not(feature = "local"))]
+mod sqs;
+
+use config::*;
+use connection::*;
+#[cfg(not(feature = "local"))]
+use direct::*;
+use gateway::*;
+#[cfg(not(feature = "local"))]
+use jobs::*;
 use lambda::*;
+#[cfg(not(feature = "local"))]
 use lambda_runtime::{service_fn, LambdaEvent};
-use openssl::ssl::{SslConnector, SslMethod};
-use postgres_openssl::MakeTlsConnector;
+#[cfg(not(feature = "local"))]
+use scheduled::*;
 use serde_json::{json, Value};
+#[cfg(not(feature = "local"))]
+use sqs::*;
 use tracing::{event, Level};
-use tracing_subscriber;
 use wedding_funcs::*;
 
 type StdErr = Box<dyn std::error::Error + Send + Sync>;
 
+/// Everything shared across invocations, set up once when the function starts
+struct App {
+    config: Config,
+    connection: Connection,
+    mail: Option<Mail<Box<dyn Mailer>>>,
+}
+
+impl App {
+    fn new() -> Result<Self, ConfigErr> {
+        let config = Config::from_env()?;
+        let connection = Connection::new(&config)?;
+        let mail = config.mail()?;
+        Ok(Self {
+            config,
+            connection,
+            mail,
+        })
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), StdErr> {
     tracing_subscriber::fmt()
@@ -21,72 +62,36 @@ async fn main() -> Result<(), StdErr> {
         .without_time()
         .json()
         .init();
-    let func = service_fn(handle);
-    lambda_runtime::run(func).await?;
-    Ok(())
-}
-
-#[tracing::instrument(skip_all, fields(body))]
-async fn handle(event: LambdaEvent<Value>) -> Result<Value, StdErr> {
-    let (event, _context) = event.into_parts();
-    let body = event.get("body");
-    tracing::Span::current().record("body", format!("{:?}", body));
 
-    event!(Level::INFO, "Lambda function called");
+    // A bad config is reported on every request rather than failing the function's init
+    let app = App::new();
 
-    if let None = body {
-        event!(Level::WARN, "Missing body");
-        return Ok(HttpError::from(ApiErr::ArgumentErr("Missing body".into())).into());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args, app?).await;
     }
 
-    let body_str = body.expect("Should hanlde None").as_str();
-
-    if let None = body_str {
-        event!(Level::WARN, "Non-string body");
-        return Ok(HttpError::from(ApiErr::ArgumentErr("Body is not a string".into())).into());
-    }
-
-    let params: Result<Payload, _> = serde_json::from_str(body_str.expect("Should handle None"));
-
-    if let Err(err) = params {
-        let err: HttpError = HttpError::from(ApiErr::ArgumentErr(format!(
-            "Could not parse into a function with parameters: {}",
-            err
-        )));
-        event!(Level::WARN, "Invalid parameters");
-        return Ok(err.into());
+    if let Err(err) = &app {
+        event!(Level::ERROR, "Invalid configuration {}", err);
     }
 
-    let params = params.expect("Should handle err");
+    #[cfg(feature = "local")]
+    local::serve(app).await?;
 
-    let cert_path =
-        std::env::var("SSL_CERT_PATH").expect("SSL root certificat path should be defined in env");
+    #[cfg(not(feature = "local"))]
+    lambda_runtime::run(service_fn(|event| handle(event, app.as_ref()))).await?;
 
-    let mut builder = SslConnector::builder(SslMethod::tls()).unwrap();
-    builder.set_ca_file(cert_path).unwrap();
-    let connector = MakeTlsConnector::new(builder.build());
-
-    let uri = env::var("WED_POSTGRES_URI").expect("Uri should be defined in env");
-    let (client, connection) = tokio_postgres::connect(&uri, connector)
-        .await
-        .expect("Connection should not fail");
-
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            eprintln!("connection error: {}", e);
-        }
-    });
-    let db = DB { client: &client };
-    let result = handle_request(params, db).await;
+    Ok(())
+}
 
-    match result {
-        Ok(value) => {
-            event!(Level::INFO, "function result success {:?}", value);
-            Ok(lambda_response(json!({ "data": value }), 200))
-        }
-        Err(err) => {
-            event!(Level::ERROR, "function result err {}", err);
-            Ok(HttpError::from(err).into())
-        }
+/// Dispatches each kind of event to its own handler, so background jobs share the api's image
+#[cfg(not(feature = "local"))]
+async fn handle(event: LambdaEvent<Value>, app: Result<&App, &ConfigErr>) -> Result<Value, StdErr> {
+    let (event, _context) = event.into_parts();
+    match Invocation::of(&event) {
+        Invocation::Scheduled => handle_scheduled(app).await,
+        Invocation::Sqs(messages) => handle_sqs(messages, app).await,
+        Invocation::Direct => handle_direct(event, app).await,
+        Invocation::ApiGateway => handle_event(event, app).await,
     }
 }
@@ -0,0 +1,732 @@
+use super::*;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Debug)]
+struct MemoryInvitee {
+    invitee: InviteeDTO,
+    invitation_opened: bool,
+}
+
+#[derive(Clone, Debug)]
+struct MemoryOutbox {
+    message: OutboxMessage,
+    status: OutboxStatus,
+    due: SystemTime,
+}
+
+#[derive(Clone, Debug)]
+struct MemoryToken {
+    token: String,
+    household: String,
+    revoked: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+struct MemoryData {
+    invitees: Vec<MemoryInvitee>,
+    relations: Vec<(String, String)>,
+    emails: HashMap<String, String>,
+    tags: HashMap<String, Vec<String>>,
+    invites_sent: Vec<(String, SystemTime)>,
+    outbox: Vec<MemoryOutbox>,
+    reminders: Vec<(String, ReminderSegment, SystemTime)>,
+    reminder_settings: Vec<ReminderSettings>,
+    rsvp_deadlines: HashMap<String, DateTime<Utc>>,
+    tokens: Vec<MemoryToken>,
+}
+
+/// In-memory repo for tests and demos, which needs no database
+#[derive(Debug, Default)]
+pub struct MemoryRepo {
+    data: Arc<Mutex<MemoryData>>,
+    /// Set for an open transaction, which is written back here on commit
+    committed: Option<Arc<Mutex<MemoryData>>>,
+}
+
+impl MemoryRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A repo holding the same guests as `seed.sql`
+    pub fn seeded() -> Self {
+        let repo = Self::new();
+        repo.insert_invitee("2e53abf4-323a-48ce-9ec2-0c0850c15523", "David", "Kwong");
+        repo.insert_invitee("8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097", "Mia", "Huang");
+        repo.insert_invitee("0c6c0fc4-146f-4e2a-b081-7f3ec5281290", "Joseph", "Kwong");
+        repo.insert_invitee("e01601bb-0647-410f-91b5-ca4df097c175", "Willian", "Kwong");
+        repo.insert_relation(
+            "2e53abf4-323a-48ce-9ec2-0c0850c15523",
+            "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097",
+        );
+        repo.insert_relation(
+            "2e53abf4-323a-48ce-9ec2-0c0850c15523",
+            "0c6c0fc4-146f-4e2a-b081-7f3ec5281290",
+        );
+        repo
+    }
+
+    pub fn insert_invitee(&self, id: &str, fname: &str, lname: &str) {
+        self.data().invitees.push(MemoryInvitee {
+            invitee: InviteeDTO {
+                id: id.to_string(),
+                fname: fname.to_string(),
+                lname: lname.to_string(),
+                rsvp: Rsvp::Unknown,
+                dietary_requirements: "".to_string(),
+                dietary: Some(DietaryProfile::default()),
+            },
+            invitation_opened: false,
+        });
+    }
+
+    pub fn insert_relation(&self, parent: &str, child: &str) {
+        self.data()
+            .relations
+            .push((parent.to_string(), child.to_string()));
+    }
+
+    /// Every queued message with its status, in the order they were queued
+    pub fn outbox(&self) -> Vec<(OutboxMessage, OutboxStatus)> {
+        self.data()
+            .outbox
+            .iter()
+            .map(|e| (e.message.clone(), e.status))
+            .collect()
+    }
+
+    /// Makes every pending message due, as if their backoff had passed
+    pub fn retry_now(&self) {
+        for entry in &mut self.data().outbox {
+            entry.due = SystemTime::now();
+        }
+    }
+
+    pub fn reminders_sent(&self, household: &str) -> usize {
+        self.data()
+            .reminders
+            .iter()
+            .filter(|(e, _, _)| e == household)
+            .count()
+    }
+
+    pub fn has_reminder_settings(&self, household: &str) -> bool {
+        self.data()
+            .reminder_settings
+            .iter()
+            .any(|e| e.household == household)
+    }
+
+    pub fn is_opened(&self, id: &str) -> Option<bool> {
+        self.data()
+            .invitees
+            .iter()
+            .find(|e| e.invitee.id == id)
+            .map(|e| e.invitation_opened)
+    }
+
+    pub fn is_invite_sent(&self, id: &str) -> bool {
+        self.data().invites_sent.iter().any(|(e, _)| e == id)
+    }
+
+    fn data(&self) -> MutexGuard<'_, MemoryData> {
+        self.data.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl RelationRepo for MemoryRepo {
+    async fn get_dependents(&self, id: &str) -> Result<Vec<String>, RepoErr> {
+        Ok(self
+            .data()
+            .relations
+            .iter()
+            .filter(|(parent, _)| parent == id)
+            .map(|(_, child)| child.clone())
+            .collect())
+    }
+
+    async fn get_parent(&self, id: &str) -> Result<Option<String>, RepoErr> {
+        Ok(self
+            .data()
+            .relations
+            .iter()
+            .find(|(_, child)| child == id)
+            .map(|(parent, _)| parent.clone()))
+    }
+
+    async fn add_relation(&self, parent: &str, child: &str) -> Result<(), RepoErr> {
+        let mut data = self.data();
+        if parent == child || data.relations.iter().any(|(_, e)| e == child) {
+            return Err(RepoErr::DBFailure(format!(
+                "Invalid relation for {}",
+                child
+            )));
+        }
+
+        data.relations.push((parent.to_string(), child.to_string()));
+        Ok(())
+    }
+
+    async fn remove_relation(&self, parent: &str, child: &str) -> Result<(), RepoErr> {
+        let mut data = self.data();
+        let count = data.relations.len();
+        data.relations.retain(|e| e.0 != parent || e.1 != child);
+        if data.relations.len() == count {
+            return Err(RepoErr::ItemNotFound(child.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InviteeRepo for MemoryRepo {
+    async fn get_invitee_by_id(&self, id: &str) -> Result<InviteeDTO, RepoErr> {
+        self.data()
+            .invitees
+            .iter()
+            .find(|e| e.invitee.id == id)
+            .map(|e| e.invitee.clone())
+            .ok_or_else(|| RepoErr::ItemNotFound(id.to_string()))
+    }
+
+    async fn get_invitee_by_ids(&self, ids: &[&str]) -> Result<Vec<InviteeDTO>, RepoErr> {
+        Ok(self
+            .data()
+            .invitees
+            .iter()
+            .filter(|e| ids.contains(&e.invitee.id.as_str()))
+            .map(|e| e.invitee.clone())
+            .collect())
+    }
+
+    async fn update_invitee(&self, invitee: &UpdateInviteeParams) -> Result<InviteeDTO, RepoErr> {
+        let mut data = self.data();
+        let found = data
+            .invitees
+            .iter_mut()
+            .find(|e| e.invitee.id == invitee.id)
+            .ok_or_else(|| RepoErr::ItemNotFound(invitee.id.clone()))?;
+
+        found.invitee.rsvp = invitee.rsvp;
+        found.invitee.dietary_requirements = invitee.dietary_requirements.clone();
+        if invitee.dietary.is_some() {
+            found.invitee.dietary = invitee.dietary.clone();
+        }
+        Ok(found.invitee.clone())
+    }
+
+    async fn mark_opened(&self, ids: &[&str]) -> Result<(), RepoErr> {
+        for found in self
+            .data()
+            .invitees
+            .iter_mut()
+            .filter(|e| ids.contains(&e.invitee.id.as_str()))
+        {
+            found.invitation_opened = true;
+        }
+        Ok(())
+    }
+
+    async fn create_invitee(&self, invitee: &InviteeDTO) -> Result<InviteeDTO, RepoErr> {
+        let mut data = self.data();
+        if data.invitees.iter().any(|e| e.invitee.id == invitee.id) {
+            return Err(RepoErr::DBFailure(format!("Duplicate id {}", invitee.id)));
+        }
+
+        let mut invitee = invitee.clone();
+        invitee.dietary = Some(invitee.dietary.unwrap_or_default());
+        data.invitees.push(MemoryInvitee {
+            invitee: invitee.clone(),
+            invitation_opened: false,
+        });
+        Ok(invitee)
+    }
+
+    async fn edit_invitee(&self, invitee: &InviteeDTO) -> Result<InviteeDTO, RepoErr> {
+        let mut data = self.data();
+        let found = data
+            .invitees
+            .iter_mut()
+            .find(|e| e.invitee.id == invitee.id)
+            .ok_or_else(|| RepoErr::ItemNotFound(invitee.id.clone()))?;
+
+        let dietary = invitee.dietary.clone().or(found.invitee.dietary.take());
+        found.invitee = InviteeDTO {
+            dietary,
+            ..invitee.clone()
+        };
+        Ok(found.invitee.clone())
+    }
+
+    async fn delete_invitee(&self, id: &str) -> Result<(), RepoErr> {
+        let mut data = self.data();
+        let count = data.invitees.len();
+        data.invitees.retain(|e| e.invitee.id != id);
+        if data.invitees.len() == count {
+            return Err(RepoErr::ItemNotFound(id.to_string()));
+        }
+
+        data.relations
+            .retain(|(parent, child)| parent != id && child != id);
+        data.emails.remove(id);
+        data.tags.remove(id);
+        data.tokens.retain(|e| e.household != id);
+        data.rsvp_deadlines.remove(id);
+        data.reminder_settings.retain(|e| e.household != id);
+        data.reminders.retain(|(household, _, _)| household != id);
+        Ok(())
+    }
+
+    async fn get_invitation(&self, id: &str) -> Result<InvitationATO, RepoErr> {
+        let primary_invitee = self.get_invitee_by_id(id).await?;
+        let dependent_ids = self.get_dependents(id).await?;
+
+        let mut dependents = vec![];
+        for id in &dependent_ids {
+            dependents.push(self.get_invitee_by_id(id).await?);
+        }
+
+        Ok(InvitationATO {
+            primary_invitee,
+            dependents,
+        })
+    }
+}
+
+#[async_trait]
+impl GuestListRepo for MemoryRepo {
+    async fn get_guest_list(&self) -> Result<Vec<GuestListEntry>, RepoErr> {
+        let data = self.data();
+        let mut entries: Vec<GuestListEntry> = data
+            .invitees
+            .iter()
+            .map(|e| {
+                let id = &e.invitee.id;
+                let mut tags = data.tags.get(id).cloned().unwrap_or_default();
+                tags.sort();
+                GuestListEntry {
+                    invitee: e.invitee.clone(),
+                    parent: data
+                        .relations
+                        .iter()
+                        .find(|(_, child)| child == id)
+                        .map(|(parent, _)| parent.clone()),
+                    email: data.emails.get(id).cloned(),
+                    tags,
+                    invitation_opened: e.invitation_opened,
+                }
+            })
+            .collect();
+
+        entries.sort_by(|a, b| {
+            (&a.invitee.lname, &a.invitee.fname, &a.invitee.id).cmp(&(
+                &b.invitee.lname,
+                &b.invitee.fname,
+                &b.invitee.id,
+            ))
+        });
+        Ok(entries)
+    }
+
+    async fn set_email(&self, id: &str, email: Option<&str>) -> Result<(), RepoErr> {
+        let mut data = self.data();
+        match email {
+            Some(email) => data.emails.insert(id.to_string(), email.to_string()),
+            None => data.emails.remove(id),
+        };
+        Ok(())
+    }
+
+    async fn set_tags(&self, id: &str, tags: &[String]) -> Result<(), RepoErr> {
+        self.data().tags.insert(id.to_string(), tags.to_vec());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EmailRepo for MemoryRepo {
+    async fn get_unsent_emails(&self) -> Result<Vec<InviteeEmail>, RepoErr> {
+        let data = self.data();
+        let mut emails: Vec<InviteeEmail> = data
+            .emails
+            .iter()
+            .filter(|(id, _)| !data.invites_sent.iter().any(|(e, _)| e == *id))
+            .map(|(id, email)| InviteeEmail {
+                invitee: id.clone(),
+                household: data
+                    .relations
+                    .iter()
+                    .find(|(_, child)| child == id)
+                    .map(|(parent, _)| parent.clone())
+                    .unwrap_or_else(|| id.clone()),
+                email: email.clone(),
+            })
+            .collect();
+
+        emails.sort_by(|a, b| {
+            a.household
+                .cmp(&b.household)
+                .then_with(|| (a.invitee != a.household).cmp(&(b.invitee != b.household)))
+                .then_with(|| a.invitee.cmp(&b.invitee))
+        });
+        Ok(emails)
+    }
+
+    async fn mark_invite_sent(&self, invitee: &str) -> Result<(), RepoErr> {
+        let mut data = self.data();
+        if !data.emails.contains_key(invitee) {
+            return Err(RepoErr::ItemNotFound(invitee.to_string()));
+        }
+
+        data.invites_sent
+            .push((invitee.to_string(), SystemTime::now()));
+        Ok(())
+    }
+
+    async fn get_household_email(&self, household: &str) -> Result<Option<String>, RepoErr> {
+        Ok(household_email(&self.data(), household))
+    }
+}
+
+/// The primary invitee's email, otherwise the first of their dependents' by id
+fn household_email(data: &MemoryData, household: &str) -> Option<String> {
+    if let Some(email) = data.emails.get(household) {
+        return Some(email.clone());
+    }
+
+    let mut dependents: Vec<&String> = data
+        .relations
+        .iter()
+        .filter(|(parent, _)| parent == household)
+        .map(|(_, child)| child)
+        .filter(|e| data.emails.contains_key(*e))
+        .collect();
+    dependents.sort();
+
+    dependents.first().map(|e| data.emails[*e].clone())
+}
+
+#[async_trait]
+impl ReminderRepo for MemoryRepo {
+    async fn get_reminder_candidates(&self) -> Result<Vec<ReminderCandidate>, RepoErr> {
+        let data = self.data();
+        let mut candidates = vec![];
+
+        for primary in data.invitees.iter().filter(|e| {
+            e.invitee.rsvp == Rsvp::Unknown
+                && !data
+                    .relations
+                    .iter()
+                    .any(|(_, child)| *child == e.invitee.id)
+        }) {
+            let household = &primary.invitee.id;
+            let members: Vec<&String> = std::iter::once(household)
+                .chain(
+                    data.relations
+                        .iter()
+                        .filter(|(parent, _)| parent == household)
+                        .map(|(_, child)| child),
+                )
+                .collect();
+
+            let invited_at = data
+                .invites_sent
+                .iter()
+                .filter(|(id, _)| members.contains(&id))
+                .map(|(_, sent_at)| *sent_at)
+                .min();
+            let (Some(invited_at), Some(email)) = (invited_at, household_email(&data, household))
+            else {
+                continue;
+            };
+
+            let reminders: Vec<SystemTime> = data
+                .reminders
+                .iter()
+                .filter(|(e, _, _)| e == household)
+                .map(|(_, _, sent_at)| *sent_at)
+                .collect();
+
+            candidates.push(ReminderCandidate {
+                household: household.clone(),
+                email,
+                opened: primary.invitation_opened,
+                invited_at: Some(invited_at),
+                reminders_sent: reminders.len() as i32,
+                last_reminded_at: reminders.iter().max().copied(),
+                settings: data
+                    .reminder_settings
+                    .iter()
+                    .find(|e| e.household == *household)
+                    .cloned()
+                    .unwrap_or_else(|| ReminderSettings {
+                        household: household.clone(),
+                        ..ReminderSettings::default()
+                    }),
+            });
+        }
+
+        candidates.sort_by(|a, b| a.household.cmp(&b.household));
+        Ok(candidates)
+    }
+
+    async fn lock_reminder_candidate(
+        &self,
+        household: &str,
+    ) -> Result<Option<ReminderCandidate>, RepoErr> {
+        let candidates = self.get_reminder_candidates().await?;
+        Ok(candidates.into_iter().find(|e| e.household == household))
+    }
+
+    async fn record_reminder(
+        &self,
+        household: &str,
+        segment: ReminderSegment,
+    ) -> Result<(), RepoErr> {
+        self.data()
+            .reminders
+            .push((household.to_string(), segment, SystemTime::now()));
+        Ok(())
+    }
+
+    async fn set_reminder_settings(&self, settings: &ReminderSettings) -> Result<(), RepoErr> {
+        let mut data = self.data();
+        data.reminder_settings
+            .retain(|e| e.household != settings.household);
+        if settings.cadence_days.is_some() || settings.max_reminders.is_some() {
+            data.reminder_settings.push(settings.clone());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeadlineRepo for MemoryRepo {
+    async fn get_rsvp_deadline(&self, household: &str) -> Result<Option<DateTime<Utc>>, RepoErr> {
+        Ok(self.data().rsvp_deadlines.get(household).copied())
+    }
+
+    async fn set_rsvp_deadline(
+        &self,
+        household: &str,
+        deadline: Option<DateTime<Utc>>,
+    ) -> Result<(), RepoErr> {
+        let mut data = self.data();
+        match deadline {
+            Some(deadline) => data.rsvp_deadlines.insert(household.to_string(), deadline),
+            None => data.rsvp_deadlines.remove(household),
+        };
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TokenRepo for MemoryRepo {
+    async fn get_token_household(&self, token: &str) -> Result<Option<String>, RepoErr> {
+        Ok(self
+            .data()
+            .tokens
+            .iter()
+            .find(|e| e.token == token && !e.revoked)
+            .map(|e| e.household.clone()))
+    }
+
+    async fn get_token(&self, household: &str) -> Result<Option<String>, RepoErr> {
+        Ok(self
+            .data()
+            .tokens
+            .iter()
+            .find(|e| e.household == household && !e.revoked)
+            .map(|e| e.token.clone()))
+    }
+
+    async fn get_or_issue_token(&self, household: &str, token: &str) -> Result<String, RepoErr> {
+        let mut data = self.data();
+        // As the db's foreign key, a token is only issued for an invitee
+        if !data.invitees.iter().any(|e| e.invitee.id == household) {
+            return Err(RepoErr::ItemNotFound(household.to_string()));
+        }
+        if let Some(active) = data
+            .tokens
+            .iter()
+            .find(|e| e.household == household && !e.revoked)
+        {
+            return Ok(active.token.clone());
+        }
+
+        data.tokens.push(MemoryToken {
+            token: token.to_string(),
+            household: household.to_string(),
+            revoked: false,
+        });
+        Ok(token.to_string())
+    }
+
+    async fn revoke_tokens(&self, household: &str) -> Result<u64, RepoErr> {
+        let mut revoked = 0;
+        for token in self
+            .data()
+            .tokens
+            .iter_mut()
+            .filter(|e| e.household == household && !e.revoked)
+        {
+            token.revoked = true;
+            revoked += 1;
+        }
+        Ok(revoked)
+    }
+}
+
+#[async_trait]
+impl OutboxRepo for MemoryRepo {
+    async fn enqueue(&self, message: &OutboxMessage) -> Result<(), RepoErr> {
+        self.data().outbox.push(MemoryOutbox {
+            message: OutboxMessage {
+                attempts: 0,
+                ..message.clone()
+            },
+            status: OutboxStatus::Pending,
+            due: SystemTime::now(),
+        });
+        Ok(())
+    }
+
+    async fn claim_pending(
+        &self,
+        limit: usize,
+        lease: Duration,
+    ) -> Result<Vec<OutboxMessage>, RepoErr> {
+        let now = SystemTime::now();
+        Ok(self
+            .data()
+            .outbox
+            .iter_mut()
+            .filter(|e| e.status == OutboxStatus::Pending && e.due <= now)
+            .take(limit)
+            .map(|e| {
+                e.message.attempts += 1;
+                e.due = now + lease;
+                e.message.clone()
+            })
+            .collect())
+    }
+
+    async fn mark_delivered(&self, id: &str) -> Result<(), RepoErr> {
+        let mut data = self.data();
+        let entry = data
+            .outbox
+            .iter_mut()
+            .find(|e| e.message.id == id)
+            .ok_or_else(|| RepoErr::ItemNotFound(id.to_string()))?;
+
+        entry.status = OutboxStatus::Delivered;
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: &str,
+        _error: &str,
+        retry_in: Option<Duration>,
+    ) -> Result<(), RepoErr> {
+        let mut data = self.data();
+        let entry = data
+            .outbox
+            .iter_mut()
+            .find(|e| e.message.id == id)
+            .ok_or_else(|| RepoErr::ItemNotFound(id.to_string()))?;
+
+        match retry_in {
+            Some(retry_in) => entry.due = SystemTime::now() + retry_in,
+            None => entry.status = OutboxStatus::Dead,
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StatsRepo for MemoryRepo {
+    async fn get_stats(&self) -> Result<RsvpStats, RepoErr> {
+        let guests = self.get_guest_list().await?;
+        let mut stats = RsvpStats::default();
+
+        for primary in guests.iter().filter(|e| e.parent.is_none()) {
+            stats.households.push(HouseholdStats {
+                household: primary.invitee.id.clone(),
+                fname: primary.invitee.fname.clone(),
+                lname: primary.invitee.lname.clone(),
+                counts: RsvpCounts::default(),
+            });
+        }
+
+        for guest in &guests {
+            let (rsvp, opened) = (guest.invitee.rsvp, guest.invitation_opened);
+            stats.overall.add(rsvp, opened);
+
+            let household = guest.parent.as_ref().unwrap_or(&guest.invitee.id);
+            if let Some(e) = stats
+                .households
+                .iter_mut()
+                .find(|e| &e.household == household)
+            {
+                e.counts.add(rsvp, opened);
+            }
+
+            for tag in &guest.tags {
+                let index = match stats.tags.iter().position(|e| &e.tag == tag) {
+                    Some(index) => index,
+                    None => {
+                        stats.tags.push(TagStats {
+                            tag: tag.clone(),
+                            counts: RsvpCounts::default(),
+                        });
+                        stats.tags.len() - 1
+                    }
+                };
+                stats.tags[index].counts.add(rsvp, opened);
+            }
+        }
+
+        stats.tags.sort_by(|a, b| a.tag.cmp(&b.tag));
+        Ok(stats)
+    }
+}
+
+#[async_trait]
+impl UnitOfWork for MemoryRepo {
+    type Tx<'t> = MemoryRepo;
+
+    async fn begin(&mut self) -> Result<MemoryRepo, RepoErr> {
+        let working = self.data().clone();
+        Ok(MemoryRepo {
+            data: Arc::new(Mutex::new(working)),
+            committed: Some(self.data.clone()),
+        })
+    }
+}
+
+#[async_trait]
+impl Commit for MemoryRepo {
+    async fn commit(self) -> Result<(), RepoErr> {
+        let committed = self
+            .committed
+            .as_ref()
+            .ok_or_else(|| RepoErr::DBFailure("No transaction to commit".to_string()))?;
+
+        let working = self.data().clone();
+        *committed.lock().unwrap_or_else(PoisonError::into_inner) = working;
+        Ok(())
+    }
+
+    async fn rollback(self) -> Result<(), RepoErr> {
+        if self.committed.is_none() {
+            return Err(RepoErr::DBFailure("No transaction to rollback".to_string()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use openssl::sha::sha256;
+use thiserror::Error;
+use tokio_postgres::Client;
+use tracing::{event, Level};
+
+#[derive(Error, Debug)]
+pub enum MigrationErr {
+    #[error("Migration {0} was changed after it was applied")]
+    ChecksumMismatch(i32),
+    #[error("Migration {0} was applied, but is not known to this version")]
+    UnknownVersion(i32),
+    #[error("Oops, an error occured: {0}")]
+    DBFailure(String),
+}
+
+impl From<tokio_postgres::Error> for MigrationErr {
+    fn from(e: tokio_postgres::Error) -> Self {
+        MigrationErr::DBFailure(e.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        sha256(self.sql.as_bytes())
+            .iter()
+            .map(|e| format!("{:02x}", e))
+            .collect()
+    }
+}
+
+/// Every migration, in the order they are applied. Once released, a migration must never be edited
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "init",
+        sql: include_str!("../migrations/0001_init.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "rsvp_check",
+        sql: include_str!("../migrations/0002_rsvp_check.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "relation_constraints",
+        sql: include_str!("../migrations/0003_relation_constraints.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "invitee_tags",
+        sql: include_str!("../migrations/0004_invitee_tags.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "dietary_profile",
+        sql: include_str!("../migrations/0005_dietary_profile.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "outbox",
+        sql: include_str!("../migrations/0006_outbox.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "outbox_retries",
+        sql: include_str!("../migrations/0007_outbox_retries.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "reminders",
+        sql: include_str!("../migrations/0008_reminders.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "rsvp_deadline",
+        sql: include_str!("../migrations/0009_rsvp_deadline.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "invitation_token",
+        sql: include_str!("../migrations/0010_invitation_token.sql"),
+    },
+];
+
+/// Checks applied migrations against the embedded ones, returning those still pending
+pub fn pending<'a>(
+    migrations: &'a [Migration],
+    applied: &[(i32, String)],
+) -> Result<Vec<&'a Migration>, MigrationErr> {
+    for (version, checksum) in applied {
+        let migration = migrations.iter().find(|e| e.version == *version);
+        match migration {
+            Some(migration) if migration.checksum() == *checksum => {}
+            Some(_) => return Err(MigrationErr::ChecksumMismatch(*version)),
+            None => return Err(MigrationErr::UnknownVersion(*version)),
+        }
+    }
+
+    Ok(migrations
+        .iter()
+        .filter(|e| !applied.iter().any(|(version, _)| *version == e.version))
+        .collect())
+}
+
+/// Applies every pending migration in a single transaction, returning the ones applied
+#[tracing::instrument(skip(client))]
+pub async fn migrate(client: &mut Client) -> Result<Vec<&'static Migration>, MigrationErr> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INT NOT NULL PRIMARY KEY,
+                name TEXT NOT NULL,
+                checksum TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .await?;
+
+    let transaction = client.transaction().await?;
+
+    // Stops two runners applying the same migrations at once
+    transaction
+        .batch_execute("LOCK TABLE schema_migrations IN EXCLUSIVE MODE")
+        .await?;
+
+    let applied = transaction
+        .query("SELECT version, checksum FROM schema_migrations", &[])
+        .await?;
+    let applied: Result<Vec<(i32, String)>, _> = applied
+        .iter()
+        .map(|e| Ok::<_, tokio_postgres::Error>((e.try_get(0)?, e.try_get(1)?)))
+        .collect();
+
+    let pending = pending(MIGRATIONS, &applied?)?;
+
+    for migration in &pending {
+        event!(
+            Level::INFO,
+            version = migration.version,
+            "Applying migration"
+        );
+        transaction.batch_execute(migration.sql).await?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)",
+                &[&migration.version, &migration.name, &migration.checksum()],
+            )
+            .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            name: "first",
+            sql: "SELECT 1",
+        },
+        Migration {
+            version: 2,
+            name: "second",
+            sql: "SELECT 2",
+        },
+    ];
+
+    #[test]
+    fn should_return_pending_migrations() {
+        let applied = vec![(1, TEST_MIGRATIONS[0].checksum())];
+
+        let pending = pending(TEST_MIGRATIONS, &applied).expect("Should find pending");
+
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].version, 2);
+    }
+
+    #[test]
+    fn should_refuse_changed_migration() {
+        let applied = vec![(1, TEST_MIGRATIONS[1].checksum())];
+
+        let pending = pending(TEST_MIGRATIONS, &applied);
+
+        assert!(matches!(pending, Err(MigrationErr::ChecksumMismatch(1))));
+    }
+
+    #[test]
+    fn should_refuse_unknown_migration() {
+        let applied = vec![(3, "".to_string())];
+
+        let pending = pending(TEST_MIGRATIONS, &applied);
+
+        assert!(matches!(pending, Err(MigrationErr::UnknownVersion(3))));
+    }
+}
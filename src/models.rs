@@ -1,6 +1,200 @@
-use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use bytes::BytesMut;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio_postgres::types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
 use tokio_postgres::Row;
 
+/// An invitee's answer, stored as text in the `rsvp` column
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Rsvp {
+    Coming,
+    NotComing,
+    Maybe,
+    #[default]
+    Unknown,
+}
+
+impl Rsvp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rsvp::Coming => "Coming",
+            Rsvp::NotComing => "NotComing",
+            Rsvp::Maybe => "Maybe",
+            Rsvp::Unknown => "Unknown",
+        }
+    }
+}
+
+impl fmt::Display for Rsvp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Rsvp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Coming" => Ok(Rsvp::Coming),
+            "NotComing" => Ok(Rsvp::NotComing),
+            "Maybe" => Ok(Rsvp::Maybe),
+            "Unknown" => Ok(Rsvp::Unknown),
+            _ => Err(format!("Unknown rsvp {}", s)),
+        }
+    }
+}
+
+impl ToSql for Rsvp {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        self.as_str().to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as ToSql>::accepts(ty)
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for Rsvp {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let rsvp = <&str as FromSql>::from_sql(ty, raw)?;
+        Ok(rsvp.parse()?)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        <&str as FromSql>::accepts(ty)
+    }
+}
+
+/// Kept compatible with the old `Option<bool>` json, `Maybe` is the only answer sent as a string
+impl Serialize for Rsvp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Rsvp::Coming => serializer.serialize_bool(true),
+            Rsvp::NotComing => serializer.serialize_bool(false),
+            Rsvp::Maybe => serializer.serialize_str("maybe"),
+            Rsvp::Unknown => serializer.serialize_none(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Rsvp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RsvpJson {
+            Bool(bool),
+            Str(String),
+        }
+
+        match Option::<RsvpJson>::deserialize(deserializer)? {
+            Some(RsvpJson::Bool(true)) => Ok(Rsvp::Coming),
+            Some(RsvpJson::Bool(false)) => Ok(Rsvp::NotComing),
+            Some(RsvpJson::Str(rsvp)) => match rsvp.as_str() {
+                "coming" => Ok(Rsvp::Coming),
+                "notComing" => Ok(Rsvp::NotComing),
+                "maybe" => Ok(Rsvp::Maybe),
+                "unknown" => Ok(Rsvp::Unknown),
+                _ => Err(serde::de::Error::custom(format!("Unknown rsvp {}", rsvp))),
+            },
+            None => Ok(Rsvp::Unknown),
+        }
+    }
+}
+
+/// A guest's overall diet, stored as text in the `dietary_profile` table
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum Diet {
+    #[default]
+    None,
+    Vegetarian,
+    Vegan,
+    Pescatarian,
+    Halal,
+    Kosher,
+    /// Described in the profile's notes
+    Other,
+}
+
+impl Diet {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Diet::None => "None",
+            Diet::Vegetarian => "Vegetarian",
+            Diet::Vegan => "Vegan",
+            Diet::Pescatarian => "Pescatarian",
+            Diet::Halal => "Halal",
+            Diet::Kosher => "Kosher",
+            Diet::Other => "Other",
+        }
+    }
+}
+
+impl FromStr for Diet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "None" => Ok(Diet::None),
+            "Vegetarian" => Ok(Diet::Vegetarian),
+            "Vegan" => Ok(Diet::Vegan),
+            "Pescatarian" => Ok(Diet::Pescatarian),
+            "Halal" => Ok(Diet::Halal),
+            "Kosher" => Ok(Diet::Kosher),
+            "Other" => Ok(Diet::Other),
+            _ => Err(format!("Unknown diet {}", s)),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum AllergySeverity {
+    Mild,
+    Moderate,
+    /// Life threatening, such as anaphylaxis
+    Severe,
+}
+
+impl AllergySeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AllergySeverity::Mild => "Mild",
+            AllergySeverity::Moderate => "Moderate",
+            AllergySeverity::Severe => "Severe",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Allergy {
+    pub allergen: String,
+    pub severity: AllergySeverity,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DietaryProfile {
+    #[serde(default)]
+    pub diet: Diet,
+    #[serde(default)]
+    pub allergies: Vec<Allergy>,
+    #[serde(default)]
+    pub notes: String,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[cfg_attr(test, derive(PartialEq))]
 #[serde(rename_all = "camelCase")]
@@ -8,8 +202,12 @@ pub struct InviteeDTO {
     pub id: String,
     pub fname: String,
     pub lname: String,
-    pub rsvp: Option<bool>,
+    #[serde(default)]
+    pub rsvp: Rsvp,
     pub dietary_requirements: String,
+    /// Always read from the repo, a write without it leaves the stored profile unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dietary: Option<DietaryProfile>,
 }
 
 impl TryFrom<&Row> for InviteeDTO {
@@ -19,7 +217,7 @@ impl TryFrom<&Row> for InviteeDTO {
         let id: Result<String, _> = value.try_get(0);
         let fname: Result<String, _> = value.try_get(1);
         let lname: Result<String, _> = value.try_get(2);
-        let rsvp: Result<String, _> = value.try_get(3);
+        let rsvp: Result<Rsvp, _> = value.try_get(3);
         let dietary_requirements: Result<String, _> = value.try_get(4);
 
         let id = id.map_err(|_| "Could not convert id")?;
@@ -29,22 +227,121 @@ impl TryFrom<&Row> for InviteeDTO {
         let dietary_requirements =
             dietary_requirements.map_err(|_| "Could not convert dietary_requirements")?;
 
-        let rsvp = match rsvp.as_str() {
-            "NotComing" => Some(false),
-            "Coming" => Some(true),
-            _ => None,
-        };
-
         Ok(Self {
             id,
             fname,
             lname,
             rsvp,
             dietary_requirements,
+            dietary: None,
         })
     }
 }
 
+/// An invitee along with everything the guest list records about them
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(rename_all = "camelCase")]
+pub struct GuestListEntry {
+    pub invitee: InviteeDTO,
+    /// The primary invitee of their household, if they are a dependent
+    pub parent: Option<String>,
+    pub email: Option<String>,
+    pub tags: Vec<String>,
+    pub invitation_opened: bool,
+}
+
+impl TryFrom<&Row> for GuestListEntry {
+    type Error = &'static str;
+
+    fn try_from(value: &Row) -> Result<Self, Self::Error> {
+        let invitee = InviteeDTO::try_from(value)?;
+        let parent: Result<Option<String>, _> = value.try_get(5);
+        let email: Result<Option<String>, _> = value.try_get(6);
+        let tags: Result<Vec<String>, _> = value.try_get(7);
+        let invitation_opened: Result<bool, _> = value.try_get(8);
+
+        let parent = parent.map_err(|_| "Could not convert parent")?;
+        let email = email.map_err(|_| "Could not convert email")?;
+        let tags = tags.map_err(|_| "Could not convert tags")?;
+        let invitation_opened =
+            invitation_opened.map_err(|_| "Could not convert invitation_opened")?;
+
+        Ok(Self {
+            invitee,
+            parent,
+            email,
+            tags,
+            invitation_opened,
+        })
+    }
+}
+
+/// An invitee's email address, along with the household whose invitation they are sent
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteeEmail {
+    pub invitee: String,
+    pub household: String,
+    pub email: String,
+}
+
+/// How many invitees gave each answer, `unanswered` counting those whose rsvp is unknown
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RsvpCounts {
+    pub total: i64,
+    pub coming: i64,
+    pub not_coming: i64,
+    pub maybe: i64,
+    pub unanswered: i64,
+    pub opened_unanswered: i64,
+}
+
+impl RsvpCounts {
+    pub fn add(&mut self, rsvp: Rsvp, invitation_opened: bool) {
+        self.total += 1;
+        match rsvp {
+            Rsvp::Coming => self.coming += 1,
+            Rsvp::NotComing => self.not_coming += 1,
+            Rsvp::Maybe => self.maybe += 1,
+            Rsvp::Unknown => {
+                self.unanswered += 1;
+                if invitation_opened {
+                    self.opened_unanswered += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Counts for the household headed by the primary invitee `household`
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HouseholdStats {
+    pub household: String,
+    pub fname: String,
+    pub lname: String,
+    #[serde(flatten)]
+    pub counts: RsvpCounts,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TagStats {
+    pub tag: String,
+    #[serde(flatten)]
+    pub counts: RsvpCounts,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RsvpStats {
+    pub overall: RsvpCounts,
+    pub households: Vec<HouseholdStats>,
+    pub tags: Vec<TagStats>,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[cfg_attr(test, derive(PartialEq))]
 #[serde(rename_all = "camelCase")]
@@ -53,19 +350,176 @@ pub struct InvitationATO {
     pub dependents: Vec<InviteeDTO>,
 }
 
+/// An invitee as their household's guests see them, without their id
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(rename_all = "camelCase")]
+pub struct GuestInvitee {
+    pub fname: String,
+    pub lname: String,
+    #[serde(default)]
+    pub rsvp: Rsvp,
+    pub dietary_requirements: String,
+    /// A write without it leaves the stored profile unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dietary: Option<DietaryProfile>,
+}
+
+impl From<&InviteeDTO> for GuestInvitee {
+    fn from(a: &InviteeDTO) -> Self {
+        Self {
+            fname: a.fname.clone(),
+            lname: a.lname.clone(),
+            rsvp: a.rsvp,
+            dietary_requirements: a.dietary_requirements.clone(),
+            dietary: a.dietary.clone(),
+        }
+    }
+}
+
+/// An invitation as the guests see it, with when they can no longer change their answers. The
+/// guests send it back with their answers, which are matched to the household by position, the
+/// dependents being ordered by name.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(rename_all = "camelCase")]
+pub struct GuestInvitation {
+    pub primary_invitee: GuestInvitee,
+    pub dependents: Vec<GuestInvitee>,
+    /// Ignored when the guests send their answers
+    #[serde(default)]
+    pub deadline: Option<DateTime<Utc>>,
+}
+
+/// The household's members in the order guests see them
+fn guest_order(invitation: &InvitationATO) -> Vec<&InviteeDTO> {
+    let mut dependents: Vec<&InviteeDTO> = invitation.dependents.iter().collect();
+    dependents.sort_by(|a, b| (&a.fname, &a.lname, &a.id).cmp(&(&b.fname, &b.lname, &b.id)));
+    std::iter::once(&invitation.primary_invitee)
+        .chain(dependents)
+        .collect()
+}
+
+impl GuestInvitation {
+    pub fn new(invitation: &InvitationATO, deadline: Option<DateTime<Utc>>) -> Self {
+        let mut guests = guest_order(invitation).into_iter().map(GuestInvitee::from);
+        Self {
+            primary_invitee: guests.next().expect("Should have a primary invitee"),
+            dependents: guests.collect(),
+            deadline,
+        }
+    }
+
+    /// The household's invitation with the guests' answers, which must be for the same guests
+    pub fn answer(&self, invitation: &InvitationATO) -> Result<InvitationATO, AnswerErr> {
+        let members = guest_order(invitation);
+        let answers: Vec<&GuestInvitee> = std::iter::once(&self.primary_invitee)
+            .chain(&self.dependents)
+            .collect();
+
+        let mut answered = invitation.clone();
+        for (i, answer) in answers.iter().enumerate() {
+            let member = match members.get(i) {
+                Some(member) if member.fname == answer.fname && member.lname == answer.lname => {
+                    member
+                }
+                _ => {
+                    return Err(AnswerErr::NotInHousehold(format!(
+                        "{} {}",
+                        answer.fname, answer.lname
+                    )))
+                }
+            };
+
+            let invitee = std::iter::once(&mut answered.primary_invitee)
+                .chain(answered.dependents.iter_mut())
+                .find(|e| e.id == member.id)
+                .expect("Should be a member of the household");
+            invitee.rsvp = answer.rsvp;
+            invitee.dietary_requirements = answer.dietary_requirements.clone();
+            invitee.dietary = answer.dietary.clone();
+        }
+
+        if answers.len() < members.len() {
+            return Err(AnswerErr::Incomplete);
+        }
+        Ok(answered)
+    }
+}
+
+/// Why guests' answers could not be matched to their household
+#[derive(Debug, PartialEq, Eq)]
+pub enum AnswerErr {
+    /// An answer for someone, by name, who is not in the household or not where they were
+    NotInHousehold(String),
+    Incomplete,
+}
+
+/// An invitee to be created by the couple, who is given a fresh id
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+#[serde(rename_all = "camelCase")]
+pub struct NewInviteeATO {
+    pub fname: String,
+    pub lname: String,
+    #[serde(default)]
+    pub rsvp: Rsvp,
+    #[serde(default)]
+    pub dietary_requirements: String,
+}
+
 #[derive(Debug)]
 pub struct UpdateInviteeParams {
     pub id: String,
-    pub rsvp: Option<bool>,
+    pub rsvp: Rsvp,
     pub dietary_requirements: String,
+    pub dietary: Option<DietaryProfile>,
 }
 
 impl From<&InviteeDTO> for UpdateInviteeParams {
     fn from(a: &InviteeDTO) -> Self {
         Self {
             id: a.id.clone(),
-            rsvp: a.rsvp.clone(),
+            rsvp: a.rsvp,
             dietary_requirements: a.dietary_requirements.clone(),
+            dietary: a.dietary.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn rsvp_should_serialize_like_option_bool() {
+        assert_eq!(json!(Rsvp::Coming), json!(true));
+        assert_eq!(json!(Rsvp::NotComing), json!(false));
+        assert_eq!(json!(Rsvp::Unknown), json!(null));
+        assert_eq!(json!(Rsvp::Maybe), json!("maybe"));
+    }
+
+    #[test]
+    fn invitee_should_deserialize_legacy_rsvp() {
+        let json = json!({
+            "id": "myid",
+            "fname": "Test1",
+            "lname": "1",
+            "dietaryRequirements": ""
+        });
+
+        let invitee: InviteeDTO = serde_json::from_value(json).expect("should parse properly");
+        assert_eq!(invitee.rsvp, Rsvp::Unknown);
+
+        for (value, rsvp) in [
+            (json!(true), Rsvp::Coming),
+            (json!(false), Rsvp::NotComing),
+            (json!(null), Rsvp::Unknown),
+            (json!("maybe"), Rsvp::Maybe),
+        ] {
+            let parsed: Rsvp = serde_json::from_value(value).expect("should parse properly");
+            assert_eq!(parsed, rsvp);
         }
     }
 }
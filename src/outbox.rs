@@ -0,0 +1,357 @@
+use super::*;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{event, Level};
+use uuid::Uuid;
+
+/// Messages claimed from the outbox at a time
+pub const OUTBOX_BATCH: usize = 20;
+/// How long a claimed message is hidden from other workers while it is delivered
+pub const OUTBOX_LEASE: Duration = Duration::from_secs(5 * 60);
+/// Attempts made before a message is dead-lettered
+pub const MAX_ATTEMPTS: i32 = 8;
+const FIRST_RETRY: Duration = Duration::from_secs(2 * 60);
+
+/// An email waiting in the outbox, rendered from `invitation` as it was when the message was
+/// queued
+#[derive(Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct OutboxMessage {
+    pub id: String,
+    pub template: Template,
+    pub to: String,
+    pub invitation: InvitationATO,
+    /// Delivery attempts, including the one a claimed message is about to make
+    pub attempts: i32,
+}
+
+/// Stored as text in the outbox's `status` column
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutboxStatus {
+    Pending,
+    Delivered,
+    /// Gave up on after `MAX_ATTEMPTS`, left for the couple to look into
+    Dead,
+}
+
+impl OutboxStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutboxStatus::Pending => "pending",
+            OutboxStatus::Delivered => "delivered",
+            OutboxStatus::Dead => "dead",
+        }
+    }
+}
+
+/// What a run of the outbox worker did with the messages it claimed
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxReport {
+    pub delivered: usize,
+    pub retrying: usize,
+    pub dead: usize,
+}
+
+/// How long to wait after a failed attempt, doubling each time, or `None` once the message has
+/// had all of its attempts
+pub fn retry_delay(attempts: i32) -> Option<Duration> {
+    if attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+    Some(FIRST_RETRY * 2u32.pow(attempts.max(1) as u32 - 1))
+}
+
+/// Queues a confirmation of the household's answers, if there is an address to send it to
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_confirmation<T: EmailRepo + OutboxRepo>(
+    invitation: &InvitationATO,
+    db: &T,
+) -> Result<(), ApiErr> {
+    let household = &invitation.primary_invitee.id;
+    let email = db
+        .get_household_email(household)
+        .await
+        .map_err(ApiErr::RepoErr);
+
+    if let Err(err) = email {
+        event!(Level::ERROR, "Failed to get household email");
+        return Err(err);
+    }
+
+    let Some(email) = email.expect("Should handle err") else {
+        event!(Level::INFO, msg = "No email to confirm rsvp to", household);
+        return Ok(());
+    };
+
+    let message = OutboxMessage {
+        id: Uuid::new_v4().to_string(),
+        template: Template::Confirmation,
+        to: email,
+        invitation: invitation.clone(),
+        attempts: 0,
+    };
+
+    if let Err(err) = db.enqueue(&message).await {
+        event!(Level::ERROR, "Failed to enqueue confirmation");
+        return Err(ApiErr::RepoErr(err));
+    }
+
+    Ok(())
+}
+
+/// Delivers the outbox until no message is due, the worker run on a schedule or from the cli
+#[tracing::instrument(skip_all)]
+pub async fn deliver_outbox<
+    T: InviteeRepo + RelationRepo + OutboxRepo + DeadlineRepo + TokenRepo,
+    M: Mailer,
+>(
+    global: Option<DateTime<Utc>>,
+    db: &T,
+    mail: &Mail<M>,
+) -> Result<OutboxReport, ApiErr> {
+    let mut report = OutboxReport::default();
+
+    loop {
+        let claimed = db
+            .claim_pending(OUTBOX_BATCH, OUTBOX_LEASE)
+            .await
+            .map_err(ApiErr::RepoErr);
+
+        if let Err(err) = claimed {
+            event!(Level::ERROR, "Failed to claim pending messages");
+            return Err(err);
+        }
+        let claimed = claimed.expect("Should handle err");
+        if claimed.is_empty() {
+            break;
+        }
+
+        for message in claimed {
+            deliver_message(&message, global, db, mail, &mut report).await;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Renders a message with the household's link and deadline, as they are when it is delivered
+async fn render_message<T: InviteeRepo + RelationRepo + DeadlineRepo + TokenRepo, M: Mailer>(
+    message: &OutboxMessage,
+    global: Option<DateTime<Utc>>,
+    db: &T,
+    mail: &Mail<M>,
+) -> Result<EmailMessage, ApiErr> {
+    let household = &message.invitation.primary_invitee.id;
+    let token = household_token(household, db).await?;
+    let deadline = deadline_text(household, global, db).await?;
+
+    Ok(render_email(
+        message.template,
+        &TemplateContext {
+            invitation: &message.invitation,
+            link: mail.invitation_link(&token),
+            deadline,
+        },
+    )
+    .to(&message.to))
+}
+
+/// Sends a claimed message, a failure to render or send it being retried until it is
+/// dead-lettered, while a failure to record the outcome leaves it to be retried after its lease
+async fn deliver_message<
+    T: InviteeRepo + RelationRepo + OutboxRepo + DeadlineRepo + TokenRepo,
+    M: Mailer,
+>(
+    message: &OutboxMessage,
+    global: Option<DateTime<Utc>>,
+    db: &T,
+    mail: &Mail<M>,
+    report: &mut OutboxReport,
+) {
+    let sent = match render_message(message, global, db, mail).await {
+        Ok(email) => mail.send(&email).await.map_err(|e| e.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+
+    let err = match sent {
+        Ok(()) => {
+            if let Err(err) = db.mark_delivered(&message.id).await {
+                event!(
+                    Level::ERROR,
+                    msg = "Failed to mark message delivered",
+                    id = message.id,
+                    ?err
+                );
+                return;
+            }
+            report.delivered += 1;
+            return;
+        }
+        Err(err) => err,
+    };
+
+    let retry_in = retry_delay(message.attempts);
+    event!(
+        Level::WARN,
+        msg = "Failed to deliver message",
+        id = message.id,
+        attempts = message.attempts,
+        ?retry_in,
+        ?err
+    );
+
+    if let Err(err) = db.mark_failed(&message.id, &err, retry_in).await {
+        event!(
+            Level::ERROR,
+            msg = "Failed to record failed delivery",
+            id = message.id,
+            ?err
+        );
+        return;
+    }
+
+    match retry_in {
+        Some(_) => report.retrying += 1,
+        None => report.dead += 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+    const DEPENDENT2: &str = "0c6c0fc4-146f-4e2a-b081-7f3ec5281290";
+    const SINGLE: &str = "e01601bb-0647-410f-91b5-ca4df097c175";
+
+    fn mail() -> Mail<MemoryMailer> {
+        Mail {
+            mailer: MemoryMailer::new(),
+            from: "couple@example.com".to_string(),
+            invitation_url: "https://example.com/?token={token}".to_string(),
+        }
+    }
+
+    async fn answer(db: &mut MemoryRepo) {
+        let token = household_token(PRIMARY, db).await.unwrap();
+        let invitation = db.get_invitation(PRIMARY).await.unwrap();
+        let mut answers = GuestInvitation::new(&invitation, None);
+        answers.dependents[1].rsvp = Rsvp::Coming;
+        answers.dependents[1].dietary_requirements = "Vegan".to_string();
+        update_invitation(&token, &answers, None, db)
+            .await
+            .expect("Should update invitation");
+    }
+
+    #[tokio::test]
+    async fn should_confirm_rsvp_to_household() {
+        let mut db = MemoryRepo::seeded();
+        db.set_email(DEPENDENT, Some("mia@example.com"))
+            .await
+            .unwrap();
+        answer(&mut db).await;
+        let mail = mail();
+
+        let report = deliver_outbox(None, &db, &mail)
+            .await
+            .expect("Should deliver");
+
+        assert_eq!(report.delivered, 1);
+        let sent = mail.mailer.sent();
+        assert_eq!(sent[0].to, "mia@example.com");
+        assert!(sent[0]
+            .text
+            .contains("Mia Huang: coming, dietary requirements: Vegan"));
+        assert_eq!(db.outbox()[0].1, OutboxStatus::Delivered);
+    }
+
+    #[tokio::test]
+    async fn should_retry_then_dead_letter_undelivered_message() {
+        let mut db = MemoryRepo::seeded();
+        db.set_email(PRIMARY, Some("david@example.com"))
+            .await
+            .unwrap();
+        answer(&mut db).await;
+        let mail = mail();
+        mail.mailer.fail_for("david@example.com");
+
+        let report = deliver_outbox(None, &db, &mail).await.unwrap();
+        assert_eq!(report.retrying, 1);
+
+        // Backing off, so the next run has nothing due
+        let report = deliver_outbox(None, &db, &mail).await.unwrap();
+        assert_eq!(report, OutboxReport::default());
+
+        for _ in 1..MAX_ATTEMPTS {
+            db.retry_now();
+            deliver_outbox(None, &db, &mail).await.unwrap();
+        }
+        let (message, status) = db.outbox().remove(0);
+        assert_eq!(message.attempts, MAX_ATTEMPTS);
+        assert_eq!(status, OutboxStatus::Dead);
+    }
+
+    #[tokio::test]
+    async fn should_not_link_message_to_merged_household() {
+        let mut db = MemoryRepo::seeded();
+        db.set_email(PRIMARY, Some("david@example.com"))
+            .await
+            .unwrap();
+        answer(&mut db).await;
+        merge_households(PRIMARY, SINGLE, &mut db).await.unwrap();
+        let mail = mail();
+
+        let report = deliver_outbox(None, &db, &mail).await.unwrap();
+
+        assert_eq!(report.retrying, 1);
+        assert!(mail.mailer.sent().is_empty());
+        assert!(matches!(
+            household_token(PRIMARY, &db).await,
+            Err(ApiErr::HouseholdConflict(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_dead_letter_message_for_deleted_household() {
+        let mut db = MemoryRepo::seeded();
+        db.set_email(PRIMARY, Some("david@example.com"))
+            .await
+            .unwrap();
+        answer(&mut db).await;
+        for id in [DEPENDENT, DEPENDENT2, PRIMARY] {
+            db.delete_invitee(id).await.unwrap();
+        }
+        let mail = mail();
+
+        let report = deliver_outbox(None, &db, &mail).await.unwrap();
+        assert_eq!(report.retrying, 1);
+        for _ in 1..MAX_ATTEMPTS {
+            db.retry_now();
+            deliver_outbox(None, &db, &mail).await.unwrap();
+        }
+
+        let (message, status) = db.outbox().remove(0);
+        assert_eq!(message.attempts, MAX_ATTEMPTS);
+        assert_eq!(status, OutboxStatus::Dead);
+        assert!(mail.mailer.sent().is_empty());
+    }
+
+    #[test]
+    fn retries_should_back_off() {
+        assert_eq!(retry_delay(1), Some(Duration::from_secs(2 * 60)));
+        assert_eq!(retry_delay(3), Some(Duration::from_secs(8 * 60)));
+        assert_eq!(retry_delay(MAX_ATTEMPTS), None);
+    }
+
+    #[tokio::test]
+    async fn should_not_queue_without_email() {
+        let mut db = MemoryRepo::seeded();
+
+        answer(&mut db).await;
+
+        assert!(db.outbox().is_empty());
+    }
+}
@@ -0,0 +1,351 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+use tracing::{event, Level};
+use uuid::Uuid;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Whether a household had opened their invitation when they were reminded, which decides the
+/// reminder they are sent
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ReminderSegment {
+    Opened,
+    Unopened,
+}
+
+impl ReminderSegment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReminderSegment::Opened => "opened",
+            ReminderSegment::Unopened => "unopened",
+        }
+    }
+
+    pub fn template(&self) -> Template {
+        match self {
+            ReminderSegment::Opened => Template::Reminder,
+            ReminderSegment::Unopened => Template::UnopenedReminder,
+        }
+    }
+}
+
+/// A household's own reminder cadence and limit, `None` falling back to the [`ReminderPolicy`]
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReminderSettings {
+    pub household: String,
+    #[serde(default)]
+    pub cadence_days: Option<i32>,
+    #[serde(default)]
+    pub max_reminders: Option<i32>,
+}
+
+/// A household which has not answered, with the reminders they have been sent so far
+#[derive(Clone, Debug)]
+pub struct ReminderCandidate {
+    pub household: String,
+    pub email: String,
+    pub opened: bool,
+    /// When their invitation was first emailed, unknown for invites sent before this was recorded
+    pub invited_at: Option<SystemTime>,
+    pub reminders_sent: i32,
+    pub last_reminded_at: Option<SystemTime>,
+    pub settings: ReminderSettings,
+}
+
+impl ReminderCandidate {
+    pub fn segment(&self) -> ReminderSegment {
+        match self.opened {
+            true => ReminderSegment::Opened,
+            false => ReminderSegment::Unopened,
+        }
+    }
+}
+
+/// The cadence and limit for households without their own settings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReminderPolicy {
+    pub cadence_days: i32,
+    pub max_reminders: i32,
+}
+
+impl Default for ReminderPolicy {
+    fn default() -> Self {
+        Self {
+            cadence_days: 7,
+            max_reminders: 3,
+        }
+    }
+}
+
+impl ReminderPolicy {
+    /// A household is due a whole cadence after their invitation or last reminder, until they
+    /// have had as many reminders as they are allowed
+    pub fn is_due(&self, candidate: &ReminderCandidate, now: SystemTime) -> bool {
+        let settings = &candidate.settings;
+        if candidate.reminders_sent >= settings.max_reminders.unwrap_or(self.max_reminders) {
+            return false;
+        }
+
+        let cadence = DAY * settings.cadence_days.unwrap_or(self.cadence_days).max(1) as u32;
+        match candidate.last_reminded_at.or(candidate.invited_at) {
+            Some(since) => since + cadence <= now,
+            None => true,
+        }
+    }
+}
+
+/// The households reminded by a run of the job, by segment
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReminderReport {
+    pub opened: Vec<String>,
+    pub unopened: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Queues a reminder in the outbox for every household which is due one, recording it in the
+/// same transaction. Each household is checked again once locked, so a run which overlaps
+/// another does not remind them twice
+#[tracing::instrument(skip(db))]
+pub async fn send_reminders<T: ReminderRepo + UnitOfWork>(
+    policy: &ReminderPolicy,
+    now: SystemTime,
+    db: &mut T,
+) -> Result<ReminderReport, ApiErr> {
+    let candidates = db.get_reminder_candidates().await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = candidates {
+        event!(Level::ERROR, "Failed to get reminder candidates");
+        return Err(err);
+    }
+
+    let mut report = ReminderReport::default();
+    for candidate in candidates.expect("Should handle err") {
+        if !policy.is_due(&candidate, now) {
+            continue;
+        }
+
+        let tx = begin_transaction(db).await?;
+        let result = queue_reminder(policy, &candidate.household, now, &tx).await;
+        let result = finish_transaction(tx, result).await;
+
+        let household = candidate.household;
+        match result {
+            Err(err) => {
+                event!(
+                    Level::ERROR,
+                    msg = "Failed to queue reminder",
+                    household,
+                    ?err
+                );
+                report.failed.push(household);
+            }
+            Ok(Some(ReminderSegment::Opened)) => report.opened.push(household),
+            Ok(Some(ReminderSegment::Unopened)) => report.unopened.push(household),
+            Ok(None) => {}
+        }
+    }
+
+    Ok(report)
+}
+
+/// Queues the household's reminder when they are still due one, returning which was queued
+async fn queue_reminder<T: InviteeRepo + OutboxRepo + ReminderRepo>(
+    policy: &ReminderPolicy,
+    household: &str,
+    now: SystemTime,
+    db: &T,
+) -> Result<Option<ReminderSegment>, ApiErr> {
+    let candidate = db
+        .lock_reminder_candidate(household)
+        .await
+        .map_err(ApiErr::RepoErr);
+
+    if let Err(err) = candidate {
+        event!(Level::ERROR, "Failed to lock reminder candidate");
+        return Err(err);
+    }
+
+    let candidate = match candidate.expect("Should handle err") {
+        Some(candidate) if policy.is_due(&candidate, now) => candidate,
+        _ => return Ok(None),
+    };
+
+    let invitation = db.get_invitation(&candidate.household).await?;
+    let segment = candidate.segment();
+
+    let message = OutboxMessage {
+        id: Uuid::new_v4().to_string(),
+        template: segment.template(),
+        to: candidate.email.clone(),
+        invitation,
+        attempts: 0,
+    };
+    db.enqueue(&message).await?;
+    db.record_reminder(&candidate.household, segment).await?;
+    Ok(Some(segment))
+}
+
+/// Overrides a household's reminder cadence and limit, `None` restoring the default
+#[tracing::instrument(skip(db))]
+pub async fn set_reminder_settings<T: InviteeRepo + RelationRepo + ReminderRepo>(
+    settings: &ReminderSettings,
+    db: &T,
+) -> Result<ReminderSettings, ApiErr> {
+    if settings.cadence_days.is_some_and(|e| e < 1) {
+        return Err(ApiErr::ArgumentErr(
+            "Reminders must be at least a day apart".to_string(),
+        ));
+    }
+    if settings.max_reminders.is_some_and(|e| e < 0) {
+        return Err(ApiErr::ArgumentErr(
+            "The number of reminders can not be negative".to_string(),
+        ));
+    }
+
+    ensure_primary(&settings.household, db).await?;
+
+    if let Err(err) = db.set_reminder_settings(settings).await {
+        event!(Level::ERROR, "Failed to set reminder settings");
+        return Err(ApiErr::RepoErr(err));
+    }
+
+    Ok(settings.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+    const SINGLE: &str = "e01601bb-0647-410f-91b5-ca4df097c175";
+
+    async fn invited() -> MemoryRepo {
+        let db = MemoryRepo::seeded();
+        db.set_email(DEPENDENT, Some("mia@example.com"))
+            .await
+            .unwrap();
+        db.set_email(SINGLE, Some("willian@example.com"))
+            .await
+            .unwrap();
+        db.mark_invite_sent(DEPENDENT).await.unwrap();
+        db.mark_invite_sent(SINGLE).await.unwrap();
+        db.open_invitation(PRIMARY).await.unwrap();
+        db
+    }
+
+    fn days(days: u64) -> SystemTime {
+        SystemTime::now() + DAY * days as u32
+    }
+
+    #[tokio::test]
+    async fn should_remind_each_segment_after_cadence() {
+        let mut db = invited().await;
+        let policy = ReminderPolicy::default();
+
+        let report = send_reminders(&policy, days(1), &mut db).await.unwrap();
+        assert_eq!(report, ReminderReport::default());
+
+        let report = send_reminders(&policy, days(8), &mut db)
+            .await
+            .expect("Should send reminders");
+
+        assert_eq!(report.opened, vec![PRIMARY]);
+        assert_eq!(report.unopened, vec![SINGLE]);
+        let outbox = db.outbox();
+        assert_eq!(outbox[0].0.template, Template::Reminder);
+        assert_eq!(outbox[0].0.to, "mia@example.com");
+        assert_eq!(outbox[1].0.template, Template::UnopenedReminder);
+    }
+
+    #[tokio::test]
+    async fn reminders_should_tell_household_their_deadline() {
+        let mut db = invited().await;
+        let global: DateTime<Utc> = "2027-03-08T00:00:00Z".parse().unwrap();
+        set_rsvp_deadline(
+            &RsvpDeadline {
+                household: PRIMARY.to_string(),
+                deadline: Some("2027-03-01T00:00:00Z".parse().unwrap()),
+            },
+            &db,
+        )
+        .await
+        .unwrap();
+        send_reminders(&ReminderPolicy::default(), days(8), &mut db)
+            .await
+            .unwrap();
+        let mail = Mail {
+            mailer: MemoryMailer::new(),
+            from: "couple@example.com".to_string(),
+            invitation_url: "https://example.com/?token={token}".to_string(),
+        };
+
+        deliver_outbox(Some(global), &db, &mail).await.unwrap();
+
+        let sent = mail.mailer.sent();
+        assert_eq!(sent[0].to, "mia@example.com");
+        assert!(sent[0].text.contains("Please let us know by 1 March 2027"));
+        assert!(sent[0].html.contains("1 March 2027"));
+        assert_eq!(sent[1].to, "willian@example.com");
+        assert!(sent[1].text.contains("8 March 2027"));
+    }
+
+    #[tokio::test]
+    async fn should_stop_at_max_reminders() {
+        let mut db = invited().await;
+        let policy = ReminderPolicy::default();
+        set_reminder_settings(
+            &ReminderSettings {
+                household: SINGLE.to_string(),
+                cadence_days: Some(1),
+                max_reminders: Some(1),
+            },
+            &db,
+        )
+        .await
+        .expect("Should set reminder settings");
+
+        send_reminders(&policy, days(2), &mut db).await.unwrap();
+        let report = send_reminders(&policy, days(4), &mut db).await.unwrap();
+
+        assert!(report.unopened.is_empty());
+        assert_eq!(db.reminders_sent(SINGLE), 1);
+    }
+
+    #[tokio::test]
+    async fn should_not_remind_households_who_answered() {
+        let mut db = invited().await;
+        let mut invitation = db.get_invitation(PRIMARY).await.unwrap();
+        invitation.primary_invitee.rsvp = Rsvp::Maybe;
+        override_invitation(&invitation, &mut db).await.unwrap();
+
+        let report = send_reminders(&ReminderPolicy::default(), days(8), &mut db)
+            .await
+            .unwrap();
+
+        assert!(report.opened.is_empty());
+        assert_eq!(report.unopened, vec![SINGLE]);
+    }
+
+    #[tokio::test]
+    async fn settings_should_be_for_primary_invitee() {
+        let db = MemoryRepo::seeded();
+
+        let result = set_reminder_settings(
+            &ReminderSettings {
+                household: DEPENDENT.to_string(),
+                cadence_days: Some(3),
+                max_reminders: None,
+            },
+            &db,
+        )
+        .await;
+
+        assert!(matches!(result, Err(ApiErr::HouseholdConflict(_))));
+    }
+}
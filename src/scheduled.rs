@@ -0,0 +1,22 @@
+use super::*;
+
+/// Queues reminders then delivers the outbox, failing the invocation so Lambda reports jobs which
+/// could not run
+#[tracing::instrument(skip_all)]
+pub async fn handle_scheduled(app: Result<&App, &ConfigErr>) -> Result<Value, StdErr> {
+    event!(Level::INFO, "Scheduled event");
+
+    let app = app.map_err(|err| err.to_string())?;
+    if app.mail.is_none() {
+        event!(
+            Level::WARN,
+            "Email is not configured, skipping reminders and the outbox"
+        );
+        return Ok(json!({ "reminders": null, "outbox": null }));
+    }
+
+    let reminders = run_job(Job::SendReminders, app).await?;
+    // Reminders are queued in the outbox, so are delivered straight away
+    let outbox = run_job(Job::DeliverOutbox, app).await?;
+    Ok(json!({ "reminders": reminders, "outbox": outbox }))
+}
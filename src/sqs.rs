@@ -0,0 +1,33 @@
+use super::*;
+
+/// Runs the job in each message, reporting those which failed so only they are retried, which
+/// needs `ReportBatchItemFailures` enabled on the event source mapping
+#[tracing::instrument(skip_all, fields(messages = messages.len()))]
+pub async fn handle_sqs(
+    messages: Vec<SqsMessage>,
+    app: Result<&App, &ConfigErr>,
+) -> Result<Value, StdErr> {
+    event!(Level::INFO, "SQS event");
+
+    let app = app.map_err(|err| err.to_string())?;
+    let mut failures = vec![];
+
+    for message in messages {
+        let result = match serde_json::from_str::<Job>(&message.body) {
+            Ok(job) => run_job(job, app).await,
+            Err(err) => Err(format!("Could not parse into a job: {}", err).into()),
+        };
+
+        if let Err(err) = result {
+            event!(
+                Level::ERROR,
+                msg = "Failed to run job from queue",
+                message_id = message.message_id,
+                %err
+            );
+            failures.push(json!({ "itemIdentifier": message.message_id }));
+        }
+    }
+
+    Ok(json!({ "batchItemFailures": failures }))
+}
@@ -0,0 +1,407 @@
+use super::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing::{event, Level};
+
+/// The emails sent to guests, each rendered from the files under `templates/<name>/`
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Template {
+    Invitation,
+    /// Sent to households who opened their invitation without answering
+    Reminder,
+    /// Sent to households who have not opened their invitation
+    UnopenedReminder,
+    Confirmation,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Template::ALL
+            .into_iter()
+            .find(|e| e.name() == s)
+            .ok_or_else(|| format!("Unknown template {}", s))
+    }
+}
+
+struct TemplateFiles {
+    subject: &'static str,
+    html: &'static str,
+    text: &'static str,
+}
+
+impl Template {
+    pub const ALL: [Template; 4] = [
+        Template::Invitation,
+        Template::Reminder,
+        Template::UnopenedReminder,
+        Template::Confirmation,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Template::Invitation => "invitation",
+            Template::Reminder => "reminder",
+            Template::UnopenedReminder => "unopened_reminder",
+            Template::Confirmation => "confirmation",
+        }
+    }
+
+    fn files(&self) -> TemplateFiles {
+        macro_rules! files {
+            ($name:literal) => {
+                TemplateFiles {
+                    subject: include_str!(concat!("../templates/", $name, "/subject.txt")),
+                    html: include_str!(concat!("../templates/", $name, "/body.html")),
+                    text: include_str!(concat!("../templates/", $name, "/body.txt")),
+                }
+            };
+        }
+
+        match self {
+            Template::Invitation => files!("invitation"),
+            Template::Reminder => files!("reminder"),
+            Template::UnopenedReminder => files!("unopened_reminder"),
+            Template::Confirmation => files!("confirmation"),
+        }
+    }
+}
+
+/// What a template is rendered with, `link` being the household's link to the website
+#[derive(Debug)]
+pub struct TemplateContext<'a> {
+    pub invitation: &'a InvitationATO,
+    pub link: String,
+    pub deadline: Option<String>,
+}
+
+/// A rendered template, which is addressed to become an [`EmailMessage`]
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+impl RenderedEmail {
+    pub fn to(self, address: &str) -> EmailMessage {
+        EmailMessage {
+            to: address.to_string(),
+            subject: self.subject,
+            html: self.html,
+            text: self.text,
+        }
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn rsvp_label(rsvp: Rsvp) -> &'static str {
+    match rsvp {
+        Rsvp::Coming => "coming",
+        Rsvp::NotComing => "not coming",
+        Rsvp::Maybe => "maybe",
+        Rsvp::Unknown => "no answer yet",
+    }
+}
+
+/// Everything the caterer will be told about the invitee, empty when they have no requirements
+pub fn dietary_summary(invitee: &InviteeDTO) -> String {
+    let mut parts: Vec<String> = vec![];
+
+    if let Some(profile) = &invitee.dietary {
+        match profile.diet {
+            Diet::None => {}
+            Diet::Other => parts.push(profile.notes.trim().to_string()),
+            diet => parts.push(diet.as_str().to_lowercase()),
+        }
+        for allergy in &profile.allergies {
+            parts.push(format!(
+                "{} allergy ({})",
+                allergy.allergen.trim(),
+                allergy.severity.as_str().to_lowercase()
+            ));
+        }
+    }
+
+    let requirements = invitee.dietary_requirements.trim();
+    if !requirements.is_empty() {
+        parts.push(requirements.to_string());
+    }
+    parts.join(", ")
+}
+
+/// An invitee's answer, with their dietary requirements when they are coming
+fn response(invitee: &InviteeDTO) -> String {
+    let mut response = format!(
+        "{} {}: {}",
+        invitee.fname,
+        invitee.lname,
+        rsvp_label(invitee.rsvp)
+    );
+
+    let dietary = dietary_summary(invitee);
+    if invitee.rsvp == Rsvp::Coming && !dietary.is_empty() {
+        response.push_str(&format!(", dietary requirements: {}", dietary));
+    }
+    response
+}
+
+/// The household's first names as they are written in a greeting, "David, Mia and Joseph"
+fn first_names(invitation: &InvitationATO) -> String {
+    let names: Vec<&str> = std::iter::once(&invitation.primary_invitee)
+        .chain(&invitation.dependents)
+        .map(|e| e.fname.as_str())
+        .collect();
+
+    match names.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// The values substituted into a template, escaped when rendering html
+fn variables(context: &TemplateContext, html: bool) -> Vec<(&'static str, String)> {
+    let escape = |value: &str| {
+        if html {
+            escape_html(value)
+        } else {
+            value.to_string()
+        }
+    };
+    let invitation = context.invitation;
+    let primary = &invitation.primary_invitee;
+
+    let responses: Vec<String> = std::iter::once(primary)
+        .chain(&invitation.dependents)
+        .map(|e| {
+            let answer = escape(&response(e));
+            if html {
+                format!("<li>{}</li>", answer)
+            } else {
+                format!("- {}", answer)
+            }
+        })
+        .collect();
+
+    vec![
+        ("first_names", escape(&first_names(invitation))),
+        (
+            "primary_name",
+            escape(&format!("{} {}", primary.fname, primary.lname)),
+        ),
+        ("link", escape(&context.link)),
+        (
+            "deadline",
+            escape(context.deadline.as_deref().unwrap_or("")),
+        ),
+        ("responses", responses.join("\n")),
+    ]
+}
+
+/// Substitutes each `{{name}}`, keeping a `{{#name}}...{{/name}}` section only when `name` is
+/// not empty. A section's tags are removed along with the line break following them.
+fn render(template: &str, variables: &[(&str, String)]) -> String {
+    let mut rendered = template.to_string();
+
+    for (name, value) in variables {
+        let open = format!("{{{{#{}}}}}", name);
+        let close = format!("{{{{/{}}}}}", name);
+
+        while let Some(start) = rendered.find(&open) {
+            let Some(end) = rendered[start..].find(&close).map(|e| e + start) else {
+                break;
+            };
+            let after_open =
+                start + open.len() + usize::from(rendered[start + open.len()..].starts_with('\n'));
+            let after_close =
+                end + close.len() + usize::from(rendered[end + close.len()..].starts_with('\n'));
+
+            let section = if value.is_empty() {
+                String::new()
+            } else {
+                rendered[after_open..end].to_string()
+            };
+            rendered.replace_range(start..after_close, &section);
+        }
+
+        rendered = rendered.replace(&format!("{{{{{}}}}}", name), value);
+    }
+
+    rendered
+}
+
+pub fn render_email(template: Template, context: &TemplateContext) -> RenderedEmail {
+    let files = template.files();
+    let text = variables(context, false);
+
+    RenderedEmail {
+        subject: render(files.subject, &text).trim().to_string(),
+        html: render(files.html, &variables(context, true)),
+        text: render(files.text, &text),
+    }
+}
+
+/// Stands in for the token of a household who has not been issued one, so a preview writes nothing
+pub const PREVIEW_TOKEN: &str = "not-issued-yet";
+
+/// Renders a template for a household, as it would be sent to them, with a placeholder link when
+/// they have no token yet
+#[tracing::instrument(skip(db, mail))]
+pub async fn preview_email<T: InviteeRepo + DeadlineRepo + TokenRepo, M: Mailer>(
+    template: Template,
+    id: &str,
+    global: Option<DateTime<Utc>>,
+    db: &T,
+    mail: &Mail<M>,
+) -> Result<RenderedEmail, ApiErr> {
+    let invitation = preview_invitation(id, db).await?;
+    let token = db
+        .get_token(&invitation.primary_invitee.id)
+        .await
+        .map_err(ApiErr::RepoErr);
+
+    if let Err(err) = token {
+        event!(Level::ERROR, "Failed to get token for preview");
+        return Err(err);
+    }
+
+    let token = token.expect("Should handle err");
+    let link = mail.invitation_link(token.as_deref().unwrap_or(PREVIEW_TOKEN));
+    let deadline = deadline_text(&invitation.primary_invitee.id, global, db).await?;
+
+    Ok(render_email(
+        template,
+        &TemplateContext {
+            invitation: &invitation,
+            link,
+            deadline,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+
+    /// Compares against `templates/snapshots/<name>.snap`, which is only written when
+    /// `UPDATE_SNAPSHOTS` is set, so a missing snapshot fails rather than passing unchecked
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("templates/snapshots")
+            .join(format!("{}.snap", name));
+
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+
+        let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+            panic!(
+                "Snapshot {} is missing, run with UPDATE_SNAPSHOTS=1 to write it",
+                path.display()
+            )
+        });
+        assert_eq!(
+            actual,
+            expected,
+            "Snapshot {} changed, rerun with UPDATE_SNAPSHOTS=1 to accept",
+            path.display()
+        );
+    }
+
+    async fn invitation() -> InvitationATO {
+        let db = MemoryRepo::seeded();
+        let mut mia = db.get_invitee_by_id(DEPENDENT).await.unwrap();
+        mia.rsvp = Rsvp::Coming;
+        mia.dietary_requirements = "No mushrooms".to_string();
+        mia.dietary = Some(DietaryProfile {
+            diet: Diet::Vegetarian,
+            allergies: vec![Allergy {
+                allergen: "Peanuts".to_string(),
+                severity: AllergySeverity::Severe,
+            }],
+            notes: String::new(),
+        });
+        db.edit_invitee(&mia).await.unwrap();
+        db.get_invitation(PRIMARY).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn templates_should_match_snapshots() {
+        let invitation = invitation().await;
+        let context = TemplateContext {
+            invitation: &invitation,
+            link: "https://example.com/?token=4f2a9c".to_string(),
+            deadline: Some("1 March 2027".to_string()),
+        };
+
+        for template in Template::ALL {
+            let email = render_email(template, &context);
+            assert_snapshot(
+                template.name(),
+                &format!(
+                    "subject: {}\n\n--- html\n{}\n--- text\n{}",
+                    email.subject, email.html, email.text
+                ),
+            );
+        }
+    }
+
+    #[test]
+    fn should_drop_empty_sections_and_escape_html() {
+        let variables = [
+            ("name", "Tom & Jerry".to_string()),
+            ("deadline", String::new()),
+        ];
+
+        assert_eq!(
+            render(
+                "Hi {{name}}\n{{#deadline}}\nBy {{deadline}}\n{{/deadline}}\nBye\n",
+                &variables
+            ),
+            "Hi Tom & Jerry\nBye\n"
+        );
+        assert_eq!(escape_html("Tom & <Jerry>"), "Tom &amp; &lt;Jerry&gt;");
+    }
+
+    #[tokio::test]
+    async fn preview_should_not_issue_token() {
+        let db = MemoryRepo::seeded();
+        let mail = Mail {
+            mailer: MemoryMailer::new(),
+            from: "couple@example.com".to_string(),
+            invitation_url: "https://example.com/?token={token}".to_string(),
+        };
+
+        let preview = preview_email(Template::Invitation, PRIMARY, None, &db, &mail)
+            .await
+            .expect("Should preview email");
+
+        assert!(preview.text.contains(PREVIEW_TOKEN));
+        assert_eq!(db.get_token(PRIMARY).await.unwrap(), None);
+
+        let token = household_token(PRIMARY, &db).await.unwrap();
+        let preview = preview_email(Template::Invitation, PRIMARY, None, &db, &mail)
+            .await
+            .unwrap();
+        assert!(preview.text.contains(&token));
+    }
+}
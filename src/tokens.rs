@@ -0,0 +1,217 @@
+use super::*;
+use openssl::rand::rand_bytes;
+use serde::Serialize;
+use tracing::{event, Level};
+
+/// 256 random bits, so tokens can not be guessed
+const TOKEN_BYTES: usize = 32;
+
+/// The token in a household's invitation link, given to the couple so they can share it
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitationToken {
+    pub household: String,
+    pub token: String,
+}
+
+/// A new token from a cryptographically secure generator, hex encoded
+pub fn generate_token() -> Result<String, ApiErr> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+
+    if let Err(err) = rand_bytes(&mut bytes) {
+        event!(Level::ERROR, msg = "Failed to generate token", ?err);
+        return Err(ApiErr::Unavailable(err.to_string()));
+    }
+
+    Ok(bytes.iter().map(|e| format!("{:02x}", e)).collect())
+}
+
+/// The household an invitation link is for, refusing unknown and revoked tokens, and tokens of
+/// an invitee who has since become someone's dependent
+pub async fn token_household<T: RelationRepo + TokenRepo>(
+    token: &str,
+    db: &T,
+) -> Result<String, ApiErr> {
+    let household = db.get_token_household(token).await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = household {
+        event!(Level::ERROR, "Failed to find household for token");
+        return Err(err);
+    }
+
+    let household = match household.expect("Should handle err") {
+        Some(household) => household,
+        None => {
+            event!(Level::WARN, "Attempted to use an invalid token");
+            return Err(ApiErr::InvalidToken);
+        }
+    };
+
+    let parent = db.get_parent(&household).await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = parent {
+        event!(Level::ERROR, "Failed to find parent");
+        return Err(err);
+    }
+
+    if parent.expect("Should handle err").is_some() {
+        event!(Level::WARN, "Attempted to use the token of a dependent");
+        return Err(ApiErr::InvalidToken);
+    }
+
+    Ok(household)
+}
+
+/// The household's active token, issuing one when they have none. A household whose primary
+/// invitee has since become a dependent is refused, as a token for them would be a dead link
+pub async fn household_token<T: InviteeRepo + RelationRepo + TokenRepo>(
+    household: &str,
+    db: &T,
+) -> Result<String, ApiErr> {
+    ensure_primary(household, db).await?;
+
+    let token = db
+        .get_or_issue_token(household, &generate_token()?)
+        .await
+        .map_err(ApiErr::RepoErr);
+
+    if let Err(err) = token {
+        event!(Level::ERROR, msg = "Failed to get token", household);
+        return Err(err);
+    }
+
+    token
+}
+
+/// The household's token for the couple, issuing one when they have none
+#[tracing::instrument(skip(db))]
+pub async fn get_invitation_token<T: InviteeRepo + RelationRepo + TokenRepo>(
+    household: &str,
+    db: &T,
+) -> Result<InvitationToken, ApiErr> {
+    Ok(InvitationToken {
+        household: household.to_string(),
+        token: household_token(household, db).await?,
+    })
+}
+
+/// Replaces the household's token, so the link they were sent stops working
+#[tracing::instrument(skip(db))]
+pub async fn rotate_invitation_token<T: UnitOfWork>(
+    household: &str,
+    db: &mut T,
+) -> Result<InvitationToken, ApiErr> {
+    let token = generate_token()?;
+    let tx = begin_transaction(db).await?;
+    let result = reissue_token(household, &token, &tx).await;
+    finish_transaction(tx, result).await
+}
+
+async fn reissue_token<T: InviteeRepo + RelationRepo + TokenRepo>(
+    household: &str,
+    token: &str,
+    db: &T,
+) -> Result<InvitationToken, ApiErr> {
+    ensure_primary(household, db).await?;
+
+    if let Err(err) = db.revoke_tokens(household).await {
+        event!(Level::ERROR, "Failed to revoke tokens");
+        return Err(ApiErr::RepoErr(err));
+    }
+
+    let token = db
+        .get_or_issue_token(household, token)
+        .await
+        .map_err(ApiErr::RepoErr);
+
+    if let Err(err) = token {
+        event!(Level::ERROR, "Failed to issue token");
+        return Err(err);
+    }
+
+    Ok(InvitationToken {
+        household: household.to_string(),
+        token: token.expect("Should handle err"),
+    })
+}
+
+/// Revokes the household's token, returning how many were revoked. A new one is only issued when
+/// the household is next emailed or the couple asks for it
+#[tracing::instrument(skip(db))]
+pub async fn revoke_invitation_tokens<T: InviteeRepo + RelationRepo + TokenRepo>(
+    household: &str,
+    db: &T,
+) -> Result<u64, ApiErr> {
+    ensure_primary(household, db).await?;
+
+    let revoked = db.revoke_tokens(household).await.map_err(ApiErr::RepoErr);
+
+    if let Err(err) = revoked {
+        event!(Level::ERROR, "Failed to revoke tokens");
+        return Err(err);
+    }
+
+    revoked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: &str = "2e53abf4-323a-48ce-9ec2-0c0850c15523";
+    const DEPENDENT: &str = "8a0bf7ec-4e44-4d2f-9a3f-bdb6e7bd3097";
+
+    #[test]
+    fn tokens_should_be_random_and_long() {
+        let token = generate_token().expect("Should generate token");
+
+        assert_eq!(token.len(), TOKEN_BYTES * 2);
+        assert!(token.chars().all(|e| e.is_ascii_hexdigit()));
+        assert_ne!(token, generate_token().unwrap());
+    }
+
+    #[tokio::test]
+    async fn should_keep_token_until_rotated() {
+        let mut db = MemoryRepo::seeded();
+        let token = get_invitation_token(PRIMARY, &db)
+            .await
+            .expect("Should issue token");
+        assert_eq!(household_token(PRIMARY, &db).await.unwrap(), token.token);
+
+        let rotated = rotate_invitation_token(PRIMARY, &mut db)
+            .await
+            .expect("Should rotate token");
+
+        assert_ne!(rotated.token, token.token);
+        assert!(matches!(
+            fetch_invitation(&token.token, None, &db).await,
+            Err(ApiErr::InvalidToken)
+        ));
+        assert!(fetch_invitation(&rotated.token, None, &db).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn revoked_token_should_be_refused() {
+        let db = MemoryRepo::seeded();
+        let token = household_token(PRIMARY, &db).await.unwrap();
+
+        let revoked = revoke_invitation_tokens(PRIMARY, &db)
+            .await
+            .expect("Should revoke tokens");
+
+        assert_eq!(revoked, 1);
+        assert!(matches!(
+            token_household(&token, &db).await,
+            Err(ApiErr::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn tokens_should_be_for_primary_invitee() {
+        let db = MemoryRepo::seeded();
+
+        let result = get_invitation_token(DEPENDENT, &db).await;
+
+        assert!(matches!(result, Err(ApiErr::HouseholdConflict(_))));
+    }
+}
@@ -136,6 +136,25 @@ resource "aws_lambda_function" "wedding_func" {
   }
 }
 
+resource "aws_cloudwatch_event_rule" "background_jobs" {
+  name                = "wedding-background-jobs-${var.environment}"
+  description         = "Runs the function's background jobs, such as delivering the email outbox"
+  schedule_expression = "rate(5 minutes)"
+}
+
+resource "aws_cloudwatch_event_target" "background_jobs" {
+  rule = aws_cloudwatch_event_rule.background_jobs.name
+  arn  = aws_lambda_function.wedding_func.arn
+}
+
+resource "aws_lambda_permission" "events_lambda" {
+  statement_id  = "AllowExecutionFromEventBridge"
+  action        = "lambda:InvokeFunction"
+  function_name = aws_lambda_function.wedding_func.function_name
+  principal     = "events.amazonaws.com"
+  source_arn    = aws_cloudwatch_event_rule.background_jobs.arn
+}
+
 resource "aws_iam_role" "role" {
   name = "lambda-execution-role-${var.environment}"
 